@@ -27,6 +27,9 @@ pub struct Analyser {
     pub current_pass: usize,
     pub current_step: usize,
     pub current_direction: bool,
+
+    // The statistics and traces of the solver runs.
+    pub stats: SolverStats,
 }
 
 impl Analyser {
@@ -88,6 +91,7 @@ impl Analyser {
             current_pass,
             current_step,
             current_direction,
+            stats: SolverStats::default(),
         })
     }
 
@@ -276,13 +280,20 @@ impl Analyser {
             .map(|&i| self.edges[i].fact.clone())
             .collect();
 
-        let mut outputs = vec![TensorFact::new()];
+        // The node might have several output ports, each of which might be
+        // used by several edges.
+        let mut outputs = vec![];
         for &i in &self.next_edges[node.id] {
-            outputs[0] = unify(&self.edges[i].fact, &outputs[0])?;
+            let port = self.edges[i].from_out;
+            if outputs.len() <= port {
+                outputs.resize(port + 1, TensorFact::new());
+            }
+
+            outputs[port] = unify(&self.edges[i].fact, &outputs[port])?;
         }
 
-        let enriched = node.op
-            .enrich(inputs, outputs)
+        let enriched = self.stats
+            .enrich(node, inputs, outputs)
             .map_err(|e| format!("While enriching for {}: {}", node.name, e))?;
 
         let mut changed = false;
@@ -299,12 +310,13 @@ impl Analyser {
             self.edges[j].fact = unified;
         }
 
-        for (_, &j) in self.next_edges[node.id].iter().enumerate() {
-            if enriched.1.len() != 1 {
-                panic!("Analyser only supports nodes with a single output port.");
-            }
+        for &j in &self.next_edges[node.id] {
+            let port = self.edges[j].from_out;
+            let fact = match enriched.1.get(port) {
+                Some(fact) => fact,
+                None => bail!("Node {:?} doesn't have an output #{}.", node.name, port),
+            };
 
-            let fact = &enriched.1[0];
             let unified = unify(fact, &self.edges[j].fact)
                 .map_err(|e| format!(
                     "While unifying outputs of node {:?}: {}",
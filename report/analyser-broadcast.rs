@@ -0,0 +1,279 @@
+use analyser::interface::expressions::{Expression, IntoExpression};
+use analyser::interface::path::Path;
+use analyser::interface::solver::{Context, Rule, Solver};
+use analyser::types::{DimFact, Fact, ShapeFact};
+use Result;
+
+use std::fmt;
+
+/// The `broadcasts` rule.
+/// It states that the shape of an expression is obtained by broadcasting the
+/// shapes of several other expressions together, following the semantics of
+/// NumPy: the shapes are aligned on their last dimension, missing dimensions
+/// are treated as 1, and each dimension of the output is the only value other
+/// than 1 found in the inputs, or 1 if there is none.
+///
+/// It can be added to the solver via the following method:
+/// ```text
+/// solver.broadcasts(&output.shape, &[&a.shape, &b.shape]);
+/// ```
+struct BroadcastRule {
+    output: Box<Expression<Output = ShapeFact>>,
+    inputs: Vec<Box<Expression<Output = ShapeFact>>>,
+}
+
+impl BroadcastRule {
+    /// Creates a new BroadcastRule instance.
+    pub fn new(
+        output: Box<Expression<Output = ShapeFact>>,
+        inputs: Vec<Box<Expression<Output = ShapeFact>>>,
+    ) -> BroadcastRule {
+        BroadcastRule { output, inputs }
+    }
+
+    /// Computes the broadcasted value of the dimensions found at the same
+    /// position in the inputs which might have one.
+    fn broadcast(dims: &[DimFact]) -> Result<DimFact> {
+        let mut result = DimFact::Only(1);
+        let mut unknown = false;
+
+        for &dim in dims {
+            match dim {
+                DimFact::Any => unknown = true,
+                DimFact::Only(1) => (),
+                _ if result == DimFact::Only(1) => result = dim,
+                _ => result = result.unify(&dim)?,
+            }
+        }
+
+        // An unknown dimension could either be 1 or the value found in the
+        // other inputs, so we can only conclude if that value isn't 1.
+        if unknown && result == DimFact::Only(1) {
+            result = DimFact::Any;
+        }
+
+        Ok(result)
+    }
+
+    /// Returns whether a shape fact has a known rank and known dimensions.
+    fn is_settled(shape: &ShapeFact) -> bool {
+        !shape.open && shape.dims.iter().all(|d| d != &DimFact::Any)
+    }
+}
+
+impl<'rules> Rule<'rules> for BroadcastRule {
+    /// Tries to apply the rule to a given context.
+    ///
+    /// The dimensions are aligned on the right, so the inputs whose rank is
+    /// unknown could have any dimension at any position. They don't prevent
+    /// the inference of the other dimensions once the rank of the output is
+    /// known, but nothing can be inferred about them apart from their rank.
+    fn apply(&self, context: &mut Context) -> Result<(bool, Vec<Box<Rule<'rules> + 'rules>>)> {
+        let mut output = self.output.get(context)?;
+        let mut inputs = self
+            .inputs
+            .iter()
+            .map(|i| i.get(context))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Backwards, if the rank of the output is known and only one input has
+        // an unknown rank, while all the others have a lower rank, then that
+        // input must have the same rank as the output.
+        if !output.open {
+            let rank = output.dims.len();
+            let open: Vec<_> = (0..inputs.len()).filter(|&i| inputs[i].open).collect();
+            let lower = inputs.iter().all(|s| s.open || s.dims.len() < rank);
+
+            if open.len() == 1 && lower {
+                let input = &mut inputs[open[0]];
+                if input.dims.len() > rank {
+                    bail!("Input {:?} has a higher rank than the output {:?}.", input, output);
+                }
+
+                let mut dims = input.dims.clone();
+                dims.resize(rank, DimFact::Any);
+                *input = ShapeFact::closed(dims);
+            }
+        }
+
+        // Forwards, the rank of the output is the highest rank of the inputs.
+        let open = inputs.iter().any(|s| s.open);
+        if !open {
+            let rank = inputs.iter().map(|s| s.dims.len()).max().unwrap_or(0);
+            output = output.unify(&ShapeFact::closed(vec![DimFact::Any; rank]))?;
+        }
+
+        // We can't align the dimensions without knowing the rank of the output.
+        if output.open {
+            return Ok((false, vec![]));
+        }
+
+        let rank = output.dims.len();
+        if let Some(input) = inputs.iter().find(|s| !s.open && s.dims.len() > rank) {
+            bail!("Input {:?} has a higher rank than the output {:?}.", input, output);
+        }
+
+        let dim = |shape: &ShapeFact, k: usize| {
+            let len = shape.dims.len();
+            if !shape.open && k < len {
+                Some(len - 1 - k)
+            } else {
+                None
+            }
+        };
+
+        // Backwards, if a dimension of the output is 1, then so is the matching
+        // dimension of every input; and if it's something else, then an input
+        // whose dimension is unknown while all the others are 1 must match it.
+        for k in 0..rank {
+            let expected = output.dims[rank - 1 - k];
+            let present: Vec<_> = (0..inputs.len())
+                .filter_map(|i| dim(&inputs[i], k).map(|j| (i, j)))
+                .collect();
+
+            match expected {
+                DimFact::Only(1) => for &(i, j) in &present {
+                    inputs[i].dims[j] = inputs[i].dims[j].unify(&DimFact::Only(1))?;
+                },
+
+                DimFact::Any => (),
+
+                // An input of unknown rank might be the one to match it.
+                _ if open => (),
+
+                _ => {
+                    let candidates: Vec<_> = present
+                        .iter()
+                        .filter(|&&(i, j)| inputs[i].dims[j] != DimFact::Only(1))
+                        .collect();
+
+                    if let [&(i, j)] = &candidates[..] {
+                        inputs[i].dims[j] = inputs[i].dims[j].unify(&expected)?;
+                    }
+                }
+            }
+        }
+
+        // Forwards, compute the broadcasted shape from the inputs, where the
+        // inputs of unknown rank might have any dimension.
+        let dims = (0..rank)
+            .rev()
+            .map(|k| {
+                let mut present: Vec<_> = inputs
+                    .iter()
+                    .filter_map(|s| dim(s, k).map(|j| s.dims[j]))
+                    .collect();
+
+                if open {
+                    present.push(DimFact::Any);
+                }
+
+                BroadcastRule::broadcast(&present)
+            })
+            .collect::<Result<_>>()?;
+
+        output = output.unify(&ShapeFact::closed(dims))?;
+
+        for (item, shape) in self.inputs.iter().zip(inputs.iter()) {
+            item.set(context, shape.clone())?;
+        }
+
+        self.output.set(context, output.clone())?;
+
+        let settled = BroadcastRule::is_settled(&output)
+            && inputs.iter().all(|s| BroadcastRule::is_settled(s));
+
+        Ok((settled, vec![]))
+    }
+
+    /// Returns the paths that the rule depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        let mut paths = self.output.get_paths();
+        paths.extend(self.inputs.iter().flat_map(|e| e.get_paths()));
+        paths
+    }
+}
+
+impl fmt::Debug for BroadcastRule {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:?} == broadcast(", self.output)?;
+        for (i, item) in self.inputs.iter().enumerate() {
+            if i > 0 {
+                write!(formatter, ", ")?;
+            }
+            write!(formatter, "{:?}", item)?;
+        }
+        write!(formatter, ")")
+    }
+}
+
+impl<'rules> Solver<'rules> {
+    /// Ensures that the shape of an expression is the result of broadcasting
+    /// the shapes of other expressions together.
+    ///
+    /// For instance, one could write:
+    /// ```text
+    /// solver.broadcasts(&outputs[0].shape, &[&inputs[0].shape, &inputs[1].shape]);
+    /// ```
+    pub fn broadcasts<E, O, A>(&mut self, output: O, inputs: &[A]) -> &mut Solver<'rules>
+    where
+        E: Expression<Output = ShapeFact> + 'static,
+        O: IntoExpression<E>,
+        A: IntoExpression<E> + Copy,
+    {
+        let inputs: Vec<Box<Expression<Output = ShapeFact>>> = inputs
+            .iter()
+            .map(|&i| Box::new(i.into_expr()) as Box<Expression<Output = ShapeFact>>)
+            .collect();
+
+        let rule = BroadcastRule::new(Box::new(output.into_expr()), inputs);
+        self.rules.push(Box::new(rule));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::proxies::{Side, TensorsProxy};
+    use analyser::types::TensorFact;
+
+    fn fact(shape: ShapeFact) -> TensorFact {
+        TensorFact { shape, ..TensorFact::new() }
+    }
+
+    /// Broadcasts the shapes of two inputs into the shape of an output.
+    fn broadcast(a: ShapeFact, b: ShapeFact, output: ShapeFact) -> Result<ShapeFact> {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+
+        let mut solver = Solver::default();
+        solver.broadcasts(&outputs[0].shape, &[&inputs[0].shape, &inputs[1].shape]);
+
+        let (_, outputs) = solver.infer((vec![fact(a), fact(b)], vec![fact(output)]))?;
+        Ok(outputs[0].shape.clone())
+    }
+
+    #[test]
+    fn broadcast_concrete_shapes() {
+        let output = broadcast(shapefact![2, 1, 3], shapefact![4, 1], shapefact![..]).unwrap();
+        assert_eq!(output, shapefact![2, 4, 3]);
+    }
+
+    #[test]
+    fn broadcast_streamed_with_one() {
+        let output = broadcast(shapefact![S, 3], shapefact![1, 3], shapefact![..]).unwrap();
+        assert_eq!(output, shapefact![S, 3]);
+    }
+
+    #[test]
+    fn broadcast_streamed_with_other_dimension() {
+        assert!(broadcast(shapefact![S, 3], shapefact![4, 3], shapefact![..]).is_err());
+    }
+
+    #[test]
+    fn broadcast_with_open_input() {
+        let output = broadcast(shapefact![..], shapefact![5, 1], shapefact![_, _]).unwrap();
+        assert_eq!(output, shapefact![5, _]);
+    }
+}
@@ -98,7 +98,7 @@ pub fn connected_components(analyser: &Analyser) -> Result<Vec<Component>> {
 }
 
 /// Creates a new Const node with the given Tensor value.
-fn build_const_node(id: usize, name: String, tensor: TensorProto) -> Node {
+pub fn build_const_node(id: usize, name: String, tensor: TensorProto) -> Node {
     let node_def = tfpb::node()
         .name(name.clone())
         .op("Const")
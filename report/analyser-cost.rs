@@ -0,0 +1,316 @@
+use super::memory::{datatype_size, lifetimes, peak, Lifetime};
+use super::prelude::*;
+use super::Analyser;
+use ops::Attr;
+
+/// The estimated cost of a single node.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct NodeCost {
+    pub id: usize,
+    pub name: String,
+    pub op_name: String,
+
+    // The number of bytes of the outputs of the node.
+    pub bytes: Option<usize>,
+
+    // The number of multiply-accumulate operations of the node, and its
+    // total number of floating-point operations.
+    pub macs: Option<usize>,
+    pub flops: Option<usize>,
+}
+
+/// An estimation of the cost of the evaluation of a graph.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct CostReport {
+    // Whether the figures are given per chunk, because some dimensions are
+    // streamed. Streamed dimensions then count as 1.
+    pub per_chunk: bool,
+
+    // The cost of every node in the plan, in the order of evaluation.
+    pub nodes: Vec<NodeCost>,
+
+    // The sum of the bytes of every output, and the maximum number of bytes
+    // of the tensors which are alive at the same time.
+    pub total_bytes: usize,
+    pub peak_bytes: usize,
+
+    pub total_macs: usize,
+    pub total_flops: usize,
+
+    // The nodes whose cost couldn't be estimated, e.g. because their shapes
+    // aren't known.
+    pub unknown: Vec<String>,
+}
+
+/// Returns the dimensions of a shape fact, counting streamed dimensions as 1.
+fn dims(fact: &TensorFact) -> Option<Vec<usize>> {
+    if fact.shape.open {
+        return None;
+    }
+
+    fact.shape
+        .dims
+        .iter()
+        .map(|d| match d {
+            DimFact::Only(d) => Some(*d),
+            DimFact::Streamed => Some(1),
+            DimFact::Any => None,
+        })
+        .collect()
+}
+
+/// Returns the number of bytes of the tensors described by a fact.
+fn bytes(fact: &TensorFact) -> Option<usize> {
+    let size = datatype_size(fact.datatype.concretize()?)?;
+    Some(dims(fact)?.iter().product::<usize>() * size)
+}
+
+/// Returns the fact about an input of a node.
+fn input_fact(analyser: &Analyser, node: usize, port: usize) -> Option<&TensorFact> {
+    analyser.prev_edges[node].get(port).map(|&j| &analyser.edges[j].fact)
+}
+
+/// Returns the fact about an output of a node.
+fn output_fact(analyser: &Analyser, node: usize, port: usize) -> Option<&TensorFact> {
+    analyser.next_edges[node]
+        .iter()
+        .map(|&j| &analyser.edges[j])
+        .find(|e| e.from_out == port)
+        .map(|e| &e.fact)
+}
+
+/// Returns the value of a list-of-integers attribute.
+fn usize_vec(op: &Op, name: &str) -> Option<Vec<usize>> {
+    match op.get_attributes().get(name) {
+        Some(Attr::UsizeVec(v)) => Some(v.clone()),
+        Some(Attr::IsizeVec(v)) => Some(v.iter().map(|&i| i as usize).collect()),
+        _ => None,
+    }
+}
+
+/// Returns the value of a boolean attribute, which is false by default.
+fn flag(op: &Op, name: &str) -> bool {
+    match op.get_attributes().get(name) {
+        Some(Attr::Bool(b)) => *b,
+        _ => false,
+    }
+}
+
+/// Estimates the number of MACs and FLOPs of a node.
+///
+/// Only the operations which dominate the cost of our models are estimated
+/// precisely: convolutions, matrix products and pooling. Element-wise
+/// operations count as one FLOP per output element, and the other
+/// operations as zero.
+fn estimate(analyser: &Analyser, node: usize) -> Option<(usize, usize)> {
+    let op = &*analyser.nodes[node].op;
+    let output = dims(output_fact(analyser, node, 0)?)?;
+    let elements: usize = output.iter().product();
+
+    let cost = match &*analyser.nodes[node].op_name {
+        // The filter is in HWIO format, so each output element needs
+        // kh * kw * c_in multiply-accumulates.
+        "Conv2D" => {
+            let filter = dims(input_fact(analyser, node, 1)?)?;
+            let macs = elements * filter.get(0)? * filter.get(1)? * filter.get(2)?;
+            (macs, 2 * macs)
+        }
+
+        // The filter is in HWCM format, and each output element only depends
+        // on a single input channel.
+        "DepthwiseConv2dNative" => {
+            let filter = dims(input_fact(analyser, node, 1)?)?;
+            let macs = elements * filter.get(0)? * filter.get(1)?;
+            (macs, 2 * macs)
+        }
+
+        "MatMul" => {
+            let a = dims(input_fact(analyser, node, 0)?)?;
+            let k = *a.get(if flag(op, "transpose_a") { 0 } else { 1 })?;
+            let macs = elements * k;
+            (macs, 2 * macs)
+        }
+
+        "MaxPool" | "AvgPool" => {
+            let ksize = usize_vec(op, "ksize")?;
+            (0, elements * ksize.iter().product::<usize>())
+        }
+
+        "Add" | "BiasAdd" | "Sub" | "Mul" | "Div" | "Maximum" | "Minimum" | "SquaredDifference"
+        | "Relu" | "Relu6" | "Sigmoid" | "Tanh" | "Softmax" => (0, elements),
+
+        _ => (0, 0),
+    };
+
+    Some(cost)
+}
+
+impl Analyser {
+    /// Estimates the memory and the number of operations needed to evaluate
+    /// the graph, using the facts inferred by the analyser.
+    ///
+    /// When some dimensions are streamed, the figures are given per chunk.
+    pub fn estimate_cost(&self) -> CostReport {
+        let per_chunk = self
+            .edges
+            .iter()
+            .any(|e| e.fact.shape.dims.iter().any(|d| d.is_streamed()));
+
+        let lifetimes = lifetimes(self);
+        let size = |l: &Lifetime| bytes(&self.edges[l.edge].fact);
+
+        let mut nodes = vec![];
+        let mut unknown = vec![];
+
+        for &n in &self.plan {
+            let node = &self.nodes[n];
+
+            // The bytes of each output count once, even if several edges
+            // carry it to different nodes.
+            let output_bytes = lifetimes.iter().filter(|l| l.node == n).map(&size).sum::<Option<usize>>();
+            let cost = estimate(self, n);
+
+            if output_bytes.is_none() || cost.is_none() {
+                unknown.push(node.name.clone());
+            }
+
+            nodes.push(NodeCost {
+                id: n,
+                name: node.name.clone(),
+                op_name: node.op_name.clone(),
+                bytes: output_bytes,
+                macs: cost.map(|c| c.0),
+                flops: cost.map(|c| c.1),
+            });
+        }
+
+        CostReport {
+            per_chunk,
+            total_bytes: lifetimes.iter().filter_map(&size).sum(),
+            peak_bytes: peak(self, &lifetimes, |l| size(l).unwrap_or(0)),
+            total_macs: nodes.iter().filter_map(|n| n.macs).sum(),
+            total_flops: nodes.iter().filter_map(|n| n.flops).sum(),
+            nodes,
+            unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::memory::Policy;
+    use ndarray::ArrayD;
+    use tfpb;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::DT_FLOAT;
+    use {Model, Tensor};
+
+    fn constant(name: &str, shape: Vec<usize>) -> NodeDef {
+        tfpb::node()
+            .name(name.to_string())
+            .op("Const")
+            .attr("dtype", DT_FLOAT)
+            .attr("value", Tensor::F32(ArrayD::from_elem(shape, 1.)).to_pb().unwrap())
+    }
+
+    fn input() -> NodeDef {
+        tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT)
+    }
+
+    /// Analyses a graph whose input has the given shape.
+    fn analyse(graph: tfpb::graph::GraphDef, output: &str, shape: ShapeFact) -> Analyser {
+        let model = Model::new(graph).unwrap();
+        let input = model.nodes_by_name["input"];
+        let output = model.nodes_by_name[output];
+
+        let mut analyser = Analyser::new(model, output).unwrap();
+        let fact = TensorFact {
+            datatype: typefact!(DT_FLOAT),
+            shape,
+            value: valuefact!(_),
+        };
+
+        analyser.hint(input, &fact).unwrap();
+        analyser.run().unwrap();
+        analyser
+    }
+
+    fn node_cost<'a>(report: &'a CostReport, name: &str) -> &'a NodeCost {
+        report.nodes.iter().find(|n| n.name == name).unwrap()
+    }
+
+    #[test]
+    fn cost_of_conv2d() {
+        let graph = tfpb::graph()
+            .node(input())
+            .node(constant("filter", vec![3, 3, 2, 4]))
+            .node(tfpb::node()
+                .name("conv".to_string())
+                .op("Conv2D")
+                .attr("T", DT_FLOAT)
+                .attr("padding", "VALID".to_string())
+                .attr("strides", vec![1, 1, 1, 1])
+                .input("input".to_string())
+                .input("filter".to_string()));
+
+        let analyser = analyse(graph, "conv", shapefact![1, 5, 5, 2]);
+        let report = analyser.estimate_cost();
+
+        // Each of the 1 * 3 * 3 * 4 outputs needs 3 * 3 * 2 MACs.
+        let conv = node_cost(&report, "conv");
+        assert_eq!(conv.macs, Some(36 * 18));
+        assert_eq!(conv.flops, Some(2 * 36 * 18));
+        assert_eq!(conv.bytes, Some(36 * 4));
+        assert!(!report.per_chunk);
+        assert!(report.unknown.is_empty());
+
+        // The filter is stored in the graph, so only input and conv are live.
+        assert_eq!(report.peak_bytes, (50 + 36) * 4);
+        assert_eq!(report.peak_bytes, analyser.plan_memory(Policy::BestFit).unwrap().peak);
+    }
+
+    #[test]
+    fn cost_of_matmul() {
+        let graph = tfpb::graph()
+            .node(input())
+            .node(constant("weights", vec![3, 4]))
+            .node(tfpb::node()
+                .name("matmul".to_string())
+                .op("MatMul")
+                .attr("T", DT_FLOAT)
+                .attr("transpose_a", false)
+                .attr("transpose_b", false)
+                .input("input".to_string())
+                .input("weights".to_string()));
+
+        let analyser = analyse(graph, "matmul", shapefact![2, 3]);
+        let report = analyser.estimate_cost();
+
+        let matmul = node_cost(&report, "matmul");
+        assert_eq!(matmul.macs, Some(2 * 4 * 3));
+        assert_eq!(matmul.flops, Some(2 * 2 * 4 * 3));
+        assert_eq!(report.total_bytes, (6 + 12 + 8) * 4);
+        assert_eq!(report.peak_bytes, analyser.plan_memory(Policy::BestFit).unwrap().peak);
+    }
+
+    #[test]
+    fn cost_per_chunk() {
+        let graph = tfpb::graph()
+            .node(input())
+            .node(tfpb::node().name("relu".to_string()).op("Relu").attr("T", DT_FLOAT).input("input".to_string()))
+            .node(tfpb::node().name("tanh".to_string()).op("Tanh").attr("T", DT_FLOAT).input("relu".to_string()));
+
+        let analyser = analyse(graph, "tanh", shapefact![S, 3]);
+        let report = analyser.estimate_cost();
+
+        // The streamed dimension counts as a single chunk.
+        assert!(report.per_chunk);
+        assert_eq!(node_cost(&report, "relu").flops, Some(3));
+        assert_eq!(node_cost(&report, "relu").bytes, Some(3 * 4));
+        assert_eq!(report.total_flops, 6);
+        assert_eq!(report.peak_bytes, 2 * 3 * 4);
+    }
+}
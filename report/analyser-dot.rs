@@ -0,0 +1,315 @@
+use super::visualizer::{scopes, visualize, VisualEdge, VisualGraph};
+use super::Analyser;
+use super::Result;
+use escape;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// The colours used in the DOT output.
+const CONSTANT_COLOR: &str = "gray60";
+const STREAMED_COLOR: &str = "dodgerblue3";
+
+/// The options of the DOT output.
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    // The depth after which name scopes are collapsed into single nodes, so
+    // that a depth of 0 collapses every top-level scope. The scopes are
+    // never collapsed if this is None.
+    pub max_depth: Option<usize>,
+}
+
+/// Returns the compact label of the fact on an edge, e.g. `f32[S,40]`.
+fn label(edge: &VisualEdge) -> String {
+    let dims = edge.shape.join(",");
+
+    match (edge.shape.is_empty(), edge.open) {
+        (true, true) => format!("{}[..]", edge.datatype),
+        (false, true) => format!("{}[{},..]", edge.datatype, dims),
+        _ => format!("{}[{}]", edge.datatype, dims),
+    }
+}
+
+/// A node of the DOT output, which is either a node of the graph or a name
+/// scope which was collapsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Vertex {
+    Node(usize),
+    Scope(String),
+}
+
+impl Vertex {
+    /// Returns the identifier of the vertex in the DOT output.
+    fn id(&self) -> String {
+        match self {
+            Vertex::Node(n) => format!("n{}", n),
+            Vertex::Scope(s) => escape::dot(&format!("scope:{}", s)),
+        }
+    }
+}
+
+/// A cluster of the DOT output, i.e. a name scope which isn't collapsed.
+#[derive(Debug, Default)]
+struct Cluster {
+    vertices: Vec<Vertex>,
+    children: BTreeMap<String, Cluster>,
+}
+
+impl Cluster {
+    /// Adds a vertex to the cluster of the given scopes.
+    fn insert(&mut self, scopes: &[String], vertex: Vertex) {
+        match scopes.split_first() {
+            Some((scope, rest)) => self.children
+                .entry(scope.clone())
+                .or_insert_with(Cluster::default)
+                .insert(rest, vertex),
+            None => {
+                if !self.vertices.contains(&vertex) {
+                    self.vertices.push(vertex)
+                }
+            }
+        }
+    }
+}
+
+/// Writes a cluster and its children recursively.
+fn write_cluster(
+    dot: &mut String,
+    cluster: &Cluster,
+    name: Option<&str>,
+    indent: usize,
+    cluster_id: &mut usize,
+    write_vertex: &Fn(&mut String, &Vertex, usize),
+) {
+    let pad = " ".repeat(indent * 4);
+
+    if let Some(name) = name {
+        writeln!(dot, "{}subgraph cluster_{} {{", pad, cluster_id).unwrap();
+        writeln!(dot, "{}    label={};", pad, escape::dot(name)).unwrap();
+        *cluster_id += 1;
+    }
+
+    let inner = if name.is_some() { indent + 1 } else { indent };
+
+    for vertex in &cluster.vertices {
+        write_vertex(dot, vertex, inner);
+    }
+
+    for (child, cluster) in &cluster.children {
+        write_cluster(dot, cluster, Some(child), inner, cluster_id, write_vertex);
+    }
+
+    if name.is_some() {
+        writeln!(dot, "{}}}", pad).unwrap();
+    }
+}
+
+/// Converts a graph into the DOT format, e.g. for Graphviz.
+///
+/// The nodes are clustered by name scope, and the edges are labelled with
+/// the datatype and shape of the tensors which flow through them. Nodes and
+/// edges of the constant part of the graph are greyed out, and streamed
+/// edges are coloured differently.
+pub fn to_dot(graph: &VisualGraph, options: &DotOptions) -> String {
+    // Assign every node to a vertex, and every vertex to a cluster.
+    let mut vertices = HashMap::new();
+    let mut collapsed: HashMap<String, usize> = HashMap::new();
+    let mut root = Cluster::default();
+
+    for node in &graph.nodes {
+        let scopes = scopes(&node.name);
+
+        let (vertex, clusters) = match options.max_depth {
+            Some(depth) if scopes.len() > depth => {
+                *collapsed.entry(scopes[depth].clone()).or_insert(0) += 1;
+                (Vertex::Scope(scopes[depth].clone()), &scopes[..depth])
+            }
+            _ => (Vertex::Node(node.id), &scopes[..]),
+        };
+
+        root.insert(clusters, vertex.clone());
+        vertices.insert(node.id, vertex);
+    }
+
+    let mut dot = String::from("digraph G {\n    node [shape=box, style=rounded];\n");
+    let mut cluster_id = 0;
+
+    let write_vertex = |dot: &mut String, vertex: &Vertex, indent: usize| {
+        let pad = " ".repeat(indent * 4);
+
+        match vertex {
+            Vertex::Node(n) => {
+                let node = &graph.nodes[*n];
+                let name = node.name.rsplit('/').next().unwrap_or(&node.name);
+                let label = escape::dot(&format!("{}\n{}", name, node.op));
+
+                if node.component.is_some() {
+                    writeln!(
+                        dot, "{}{} [label={}, color={}, fontcolor={}];",
+                        pad, vertex.id(), label, CONSTANT_COLOR, CONSTANT_COLOR
+                    ).unwrap();
+                } else {
+                    writeln!(dot, "{}{} [label={}];", pad, vertex.id(), label).unwrap();
+                }
+            }
+
+            Vertex::Scope(scope) => {
+                let label = escape::dot(&format!("{}\n({} nodes)", scope, collapsed[scope]));
+                writeln!(dot, "{}{} [label={}, shape=box3d, style=solid];", pad, vertex.id(), label).unwrap();
+            }
+        }
+    };
+
+    write_cluster(&mut dot, &root, None, 1, &mut cluster_id, &write_vertex);
+
+    // Merge the edges between the same vertices, e.g. between two collapsed
+    // scopes, and drop the edges inside a collapsed scope.
+    let mut edges: BTreeMap<(Vertex, Vertex), Vec<&VisualEdge>> = BTreeMap::new();
+    for edge in &graph.edges {
+        if let (Some(from), Some(to)) = (edge.from_node, edge.to_node) {
+            let (from, to) = (vertices[&from].clone(), vertices[&to].clone());
+
+            if from != to {
+                edges.entry((from, to)).or_insert_with(Vec::new).push(edge);
+            }
+        }
+    }
+
+    for ((from, to), merged) in &edges {
+        let mut text = label(merged[0]);
+        if merged.len() > 1 {
+            text.push_str(&format!(" (x{})", merged.len()));
+        }
+
+        let color = if merged.iter().all(|e| e.component.is_some()) {
+            Some(CONSTANT_COLOR)
+        } else if merged.iter().any(|e| e.streamed) {
+            Some(STREAMED_COLOR)
+        } else {
+            None
+        };
+
+        match color {
+            Some(color) => writeln!(
+                dot, "    {} -> {} [label={}, color={}, fontcolor={}];",
+                from.id(), to.id(), escape::dot(&text), color, color
+            ).unwrap(),
+            None => writeln!(dot, "    {} -> {} [label={}];", from.id(), to.id(), escape::dot(&text)).unwrap(),
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Converts the graph of an analyser into the DOT format.
+pub fn analyser_to_dot(analyser: &Analyser, options: &DotOptions) -> Result<String> {
+    Ok(to_dot(&visualize(analyser, None)?, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::visualizer::{VisualNode, FORMAT_VERSION};
+
+    fn node(id: usize, name: &str, op: &str, component: Option<usize>) -> VisualNode {
+        VisualNode {
+            id,
+            name: name.to_string(),
+            op: op.to_string(),
+            group: scopes(name).pop(),
+            component,
+            profile: None,
+        }
+    }
+
+    fn edge(id: usize, from: usize, to: Option<usize>, shape: &[&str], component: Option<usize>) -> VisualEdge {
+        VisualEdge {
+            id,
+            from_node: Some(from),
+            from_out: 0,
+            to_node: to,
+            fact: String::new(),
+            datatype: "f32".to_string(),
+            shape: shape.iter().map(|d| d.to_string()).collect(),
+            open: false,
+            streamed: shape.contains(&"S"),
+            constant: component.is_some(),
+            component,
+        }
+    }
+
+    /// Builds a graph with two nested scopes `a` and `a/b`, and a constant
+    /// node `c/w` whose value feeds the output.
+    fn graph() -> VisualGraph {
+        let mut to_output = edge(4, 2, Some(4), &["2"], None);
+
+        // The value on this edge is known, but it isn't part of the constant
+        // subgraph because it is produced by a node which has an input.
+        to_output.constant = true;
+
+        VisualGraph {
+            version: FORMAT_VERSION,
+            nodes: vec![
+                node(0, "a/b/x", "Placeholder", None),
+                node(1, "a/b/y", "Relu", None),
+                node(2, "a/z", "Add", None),
+                node(3, "c/w", "Const", Some(0)),
+                node(4, "out", "Mul", None),
+            ],
+            edges: vec![
+                edge(0, 0, Some(1), &["S", "2"], None),
+                edge(1, 0, Some(2), &["S", "2"], None),
+                edge(2, 1, Some(2), &["S", "2"], None),
+                edge(3, 3, Some(4), &["2"], Some(0)),
+                to_output,
+                edge(5, 4, None, &["2"], None),
+            ],
+            groups: vec![],
+            components: vec![],
+        }
+    }
+
+    #[test]
+    fn cluster_by_scope() {
+        let dot = to_dot(&graph(), &DotOptions::default());
+
+        assert_eq!(dot.matches("subgraph cluster_").count(), 3);
+        assert!(dot.contains("label=\"a/b\";"));
+        assert!(dot.contains("n0 [label=\"x\\nPlaceholder\"];"));
+        assert_eq!(dot.matches(" -> ").count(), 5);
+        assert!(dot.contains("n0 -> n1 [label=\"f32[S,2]\", color=dodgerblue3, fontcolor=dodgerblue3];"));
+    }
+
+    #[test]
+    fn collapse_scopes() {
+        let dot = to_dot(&graph(), &DotOptions { max_depth: Some(1) });
+
+        // Only the scope a stays a cluster, and a/b becomes a single vertex.
+        assert_eq!(dot.matches("subgraph cluster_").count(), 2);
+        assert!(dot.contains("\"scope:a/b\" [label=\"a/b\\n(2 nodes)\", shape=box3d, style=solid];"));
+        assert!(!dot.contains("n0 "));
+        assert!(!dot.contains("n1 "));
+        assert!(!dot.contains("\"scope:a/b\" -> \"scope:a/b\""));
+    }
+
+    #[test]
+    fn merge_edges() {
+        let dot = to_dot(&graph(), &DotOptions { max_depth: Some(1) });
+
+        // The edges from x and y to z are merged, and the edge from x to y
+        // is inside the collapsed scope.
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        assert!(dot.contains(
+            "\"scope:a/b\" -> n2 [label=\"f32[S,2] (x2)\", color=dodgerblue3, fontcolor=dodgerblue3];"
+        ));
+    }
+
+    #[test]
+    fn grey_out_constant_subgraph() {
+        let dot = to_dot(&graph(), &DotOptions::default());
+
+        assert!(dot.contains("n3 [label=\"w\\nConst\", color=gray60, fontcolor=gray60];"));
+        assert!(dot.contains("n3 -> n4 [label=\"f32[2]\", color=gray60, fontcolor=gray60];"));
+        assert!(dot.contains("n2 -> n4 [label=\"f32[2]\"];"));
+    }
+}
@@ -0,0 +1,100 @@
+use super::prelude::*;
+use super::Analyser;
+use super::Edge;
+use super::Result;
+use Node;
+
+impl Analyser {
+    /// Adds a node to the graph without connecting it, and returns its index.
+    pub fn add_node(&mut self, mut node: Node) -> usize {
+        let id = self.nodes.len();
+        node.id = id;
+        node.inputs = vec![];
+
+        self.nodes.push(node);
+        self.prev_edges.push(vec![]);
+        self.next_edges.push(vec![]);
+
+        id
+    }
+
+    /// Adds an edge from the given output port of a node to the next input
+    /// port of another node, and returns the index of the new edge.
+    pub fn connect(&mut self, from: usize, from_out: usize, to: usize, fact: TensorFact) -> usize {
+        let id = self.edges.len();
+
+        self.edges.push(Edge {
+            id,
+            from_node: Some(from),
+            from_out,
+            to_node: Some(to),
+            fact,
+        });
+
+        self.nodes[to].inputs.push((from, Some(from_out)));
+        self.prev_edges[to].push(id);
+        self.next_edges[from].push(id);
+
+        id
+    }
+
+    /// Returns whether a node can be bypassed through one of its inputs, i.e.
+    /// whether that input exists and only the first output of the node is used.
+    pub fn can_bypass(&self, node: usize, input: usize) -> bool {
+        let source = self.prev_edges[node]
+            .get(input)
+            .and_then(|&j| self.edges[j].from_node);
+
+        source.is_some() && self.next_edges[node].iter().all(|&j| self.edges[j].from_out == 0)
+    }
+
+    /// Redirects all the successors of a node to the source of one of its
+    /// inputs, and detaches the node from the rest of the graph.
+    ///
+    /// The node is only removed by the next call to `prune_unused`, once the
+    /// execution plan has been reset. This fails without modifying the graph
+    /// if `can_bypass` doesn't hold.
+    pub fn bypass(&mut self, node: usize, input: usize) -> Result<()> {
+        if !self.can_bypass(node, input) {
+            bail!("Can't bypass {} through its input #{}.", self.nodes[node].name, input);
+        }
+
+        let source_edge = self.prev_edges[node][input];
+        let source = self.edges[source_edge].from_node.unwrap();
+        let source_out = self.edges[source_edge].from_out;
+
+        // Detach the node from its predecessors.
+        for &j in &self.prev_edges[node] {
+            if let Some(from) = self.edges[j].from_node {
+                self.next_edges[from].retain(|&k| k != j);
+            }
+        }
+
+        self.prev_edges[node].clear();
+        self.nodes[node].inputs.clear();
+
+        // Attach its successors to the source instead.
+        let successors: Vec<_> = self.next_edges[node].drain(..).collect();
+        for j in successors {
+            let edge = &mut self.edges[j];
+            edge.from_node = Some(source);
+            edge.from_out = source_out;
+
+            if let Some(target) = edge.to_node {
+                self.nodes[target]
+                    .inputs
+                    .iter_mut()
+                    .filter(|i| i.0 == node)
+                    .for_each(|i| *i = (source, Some(source_out)));
+            }
+
+            self.next_edges[source].push(j);
+        }
+
+        if self.output == node {
+            self.output = source;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use analyser::interface::path::Path;
+use analyser::interface::solver::{Context, Rule, Solver};
+use Result;
+
+use std::fmt;
+
+/// A closure which adds rules to a solver.
+pub type RulesClosure<'rules> = Box<Fn(&mut Solver<'rules>) + 'rules>;
+
+/// The `either` rule.
+/// It states that at least one of several sets of rules must hold, and
+/// commits to one of them once all the others conflict with the context.
+///
+/// It can be added to the solver via the following two methods:
+/// ```text
+/// solver.either(
+///     |solver| { solver.equals(&input.shape[0], 1); },
+///     |solver| { solver.equals(&input.shape[0], &output.shape[0]); },
+/// );
+/// solver.one_of(vec![Box::new(|solver| ...), ...]);
+/// ```
+pub struct EitherRule<'rules> {
+    candidates: Vec<RulesClosure<'rules>>,
+
+    // The paths that the rules of the candidates depend on.
+    paths: Vec<Path>,
+}
+
+impl<'rules> EitherRule<'rules> {
+    /// Creates a new EitherRule instance.
+    pub fn new(candidates: Vec<RulesClosure<'rules>>) -> EitherRule<'rules> {
+        let mut rule = EitherRule { candidates, paths: vec![] };
+
+        let mut paths = vec![];
+        for i in 0..rule.candidates.len() {
+            for candidate in rule.rules_of(i) {
+                paths.extend(candidate.get_paths().into_iter().cloned());
+            }
+        }
+
+        paths.sort();
+        paths.dedup();
+        rule.paths = paths;
+        rule
+    }
+
+    /// Returns the rules of a given candidate.
+    fn rules_of(&self, candidate: usize) -> Vec<Box<Rule<'rules> + 'rules>> {
+        let mut solver = Solver::default();
+        (self.candidates[candidate])(&mut solver);
+        solver.take_rules()
+    }
+}
+
+impl<'rules> Rule<'rules> for EitherRule<'rules> {
+    /// Tries to apply the rule to a given context.
+    ///
+    /// Each candidate is run on a copy of the context, and is discarded if
+    /// that causes a conflict. Because the context only ever gets more
+    /// specific, a discarded candidate would conflict again later, so there
+    /// is no need to remember which candidates were discarded.
+    fn apply(&self, context: &mut Context) -> Result<(bool, Vec<Box<Rule<'rules> + 'rules>>)> {
+        let mut alive = vec![];
+
+        for i in 0..self.candidates.len() {
+            let mut attempt = context.clone();
+
+            match Solver::solve(self.rules_of(i), &mut attempt, None) {
+                Ok(()) => alive.push(i),
+                Err(e) => trace!("    Either rule: discarding candidate {:?} ({}).", i, e),
+            }
+        }
+
+        match alive.len() {
+            0 => bail!("None of the candidates of {:?} is compatible with the context.", self),
+            1 => {
+                trace!("    Either rule: committing to candidate {:?}.", alive[0]);
+                Ok((true, self.rules_of(alive[0])))
+            }
+            _ => Ok((false, vec![])),
+        }
+    }
+
+    /// Returns the paths that the rule depends on, i.e. the union of the
+    /// paths that the rules of the candidates depend on.
+    ///
+    /// The rules that these rules add themselves, e.g. in a `given`, are
+    /// only known once they are applied, so they are not taken into account.
+    fn get_paths(&self) -> Vec<&Path> {
+        self.paths.iter().collect()
+    }
+}
+
+impl<'rules> fmt::Debug for EitherRule<'rules> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EitherRule {{ {:?} candidates }}", self.candidates.len())
+    }
+}
+
+impl<'rules> Solver<'rules> {
+    /// Ensures that at least one of two sets of rules holds.
+    ///
+    /// For instance, one could write:
+    /// ```text
+    /// solver.either(
+    ///     |solver| { solver.equals(&a.shape[i], 1); },
+    ///     |solver| { solver.equals(&a.shape[i], &b.shape[i]); },
+    /// );
+    /// ```
+    pub fn either<A, B>(&mut self, a: A, b: B) -> &mut Solver<'rules>
+    where
+        A: Fn(&mut Solver<'rules>) + 'rules,
+        B: Fn(&mut Solver<'rules>) + 'rules,
+    {
+        self.one_of(vec![Box::new(a), Box::new(b)])
+    }
+
+    /// Ensures that at least one of several sets of rules holds.
+    ///
+    /// The solver keeps all the candidates which are compatible with the
+    /// current facts, and only adds the rules of a candidate once it's the
+    /// last one remaining.
+    pub fn one_of(&mut self, candidates: Vec<RulesClosure<'rules>>) -> &mut Solver<'rules> {
+        let rule = EitherRule::new(candidates);
+        self.rules.push(Box::new(rule));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::proxies::{Side, TensorsProxy};
+    use analyser::types::{DimFact, ShapeFact, TensorFact};
+
+    /// Runs `either(input.shape[0] == 1, output.shape[0] == input.shape[0])`
+    /// on a vector input and a vector output.
+    fn infer(input: DimFact) -> TensorFact {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+        let vector = |dim| TensorFact { shape: ShapeFact::closed(vec![dim]), ..TensorFact::new() };
+
+        let mut solver = Solver::default();
+        solver.either(
+            |solver| { solver.equals(&inputs[0].shape[0], 1); },
+            |solver| { solver.equals(&outputs[0].shape[0], &inputs[0].shape[0]); },
+        );
+
+        let (_, mut outputs) = solver.infer((vec![vector(input)], vec![vector(DimFact::Any)])).unwrap();
+        outputs.remove(0)
+    }
+
+    #[test]
+    fn either_commits_to_remaining_candidate() {
+        assert_eq!(infer(DimFact::Only(3)).shape, ShapeFact::closed(vec![DimFact::Only(3)]));
+    }
+
+    #[test]
+    fn either_waits_while_candidates_are_alive() {
+        assert_eq!(infer(DimFact::Any).shape, ShapeFact::closed(vec![DimFact::Any]));
+    }
+
+    #[test]
+    fn either_depends_on_paths_of_candidates() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+
+        let mut solver = Solver::default();
+        solver.either(
+            |solver| { solver.equals(&inputs[0].rank, 1); },
+            |solver| { solver.equals(&outputs[0].rank, &inputs[0].rank); },
+        );
+
+        let paths: Vec<Path> = vec![vec![0, 0, 1].into(), vec![1, 0, 1].into()];
+        assert_eq!(solver.rules[0].get_paths(), paths.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn either_fails_without_candidates_left() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let input = TensorFact { shape: ShapeFact::closed(vec![DimFact::Only(3)]), ..TensorFact::new() };
+
+        let mut solver = Solver::default();
+        solver.either(
+            |solver| { solver.equals(&inputs[0].shape[0], 1); },
+            |solver| { solver.equals(&inputs[0].shape[0], 2); },
+        );
+
+        assert!(solver.infer((vec![input], vec![])).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use super::prelude::*;
+use super::Result;
+use ops::nn::local_patch::{DataFormat, Padding};
+use ops::Attr;
+use protobuf::{self, Message, RepeatedField};
+use std::fs::File;
+use std::path::Path;
+use tfpb;
+use tfpb::attr_value::{AttrValue, AttrValue_ListValue};
+use tfpb::graph::GraphDef;
+use tfpb::node_def::NodeDef;
+use tfpb::tensor_shape::{TensorShapeProto, TensorShapeProto_Dim};
+use tfpb::types::DataType;
+use {Model, Tensor};
+
+/// The attribute which stores the shapes of the outputs of a node.
+///
+/// It is the one used by TensorFlow itself, so other tools will understand
+/// it. Unknown and streamed dimensions are both stored as -1, and open shapes
+/// are stored with their known leading dimensions.
+const OUTPUT_SHAPES: &str = "_output_shapes";
+
+/// The attribute which stores, for each output of a node, whether its shape
+/// is open, i.e. whether it might have more dimensions than the stored ones.
+const OPEN_SHAPES: &str = "_open_shapes";
+
+/// The attribute which stores the datatypes of the outputs of a node, using
+/// DT_INVALID for unknown datatypes.
+const OUTPUT_TYPES: &str = "_output_types";
+
+/// The attribute which stores, for each output of a node, the index of its
+/// streamed dimension or -1 if it isn't streamed.
+///
+/// Streaming only ever happens along a single dimension, so `save` fails on
+/// facts with several streamed dimensions instead of losing some of them.
+const STREAMED_AXES: &str = "_streamed_axes";
+
+/// The attributes which store the values inferred for the outputs of a node
+/// which isn't a Const, along with the indexes of these outputs.
+const OUTPUT_VALUES: &str = "_output_values";
+const OUTPUT_VALUE_PORTS: &str = "_output_value_ports";
+
+/// Converts an attribute of an operation into its protobuf representation.
+fn attr_to_pb(attr: &Attr) -> Result<AttrValue> {
+    let mut value = AttrValue::new();
+
+    match attr {
+        Attr::DataType(dt) => value.set_field_type(*dt),
+        Attr::Bool(b) => value.set_b(*b),
+        Attr::Float(f) => value.set_f(*f),
+        Attr::I64(i) => value.set_i(*i),
+        Attr::Usize(u) => value.set_i(*u as i64),
+        Attr::Tensor(t) => value.set_tensor(t.to_pb()?),
+        Attr::Padding(Padding::Valid) => value.set_s(b"VALID".to_vec()),
+        Attr::Padding(Padding::Same) => value.set_s(b"SAME".to_vec()),
+        Attr::DataFormat(DataFormat::NHWC) => value.set_s(b"NHWC".to_vec()),
+        Attr::DataFormat(DataFormat::NCHW) => value.set_s(b"NCHW".to_vec()),
+        Attr::UsizeVec(v) => {
+            let mut list = AttrValue_ListValue::new();
+            list.set_i(v.iter().map(|&i| i as i64).collect());
+            value.set_list(list);
+        }
+        Attr::IsizeVec(v) => {
+            let mut list = AttrValue_ListValue::new();
+            list.set_i(v.iter().map(|&i| i as i64).collect());
+            value.set_list(list);
+        }
+        _ => bail!("Can't export attribute {:?}.", attr),
+    }
+
+    Ok(value)
+}
+
+/// Converts a shape fact into a TensorShapeProto, without the open marker
+/// which is stored separately.
+fn shape_to_pb(shape: &ShapeFact) -> TensorShapeProto {
+    let mut proto = TensorShapeProto::new();
+
+    let dims = shape
+        .dims
+        .iter()
+        .map(|d| {
+            let mut dim = TensorShapeProto_Dim::new();
+            dim.set_size(d.concretize().map(|i| i as i64).unwrap_or(-1));
+            dim
+        })
+        .collect();
+
+    proto.set_dim(RepeatedField::from_vec(dims));
+    proto
+}
+
+/// Converts a TensorShapeProto back into a shape fact.
+fn shape_from_pb(proto: &TensorShapeProto, streamed: i64, open: bool) -> ShapeFact {
+    if proto.get_unknown_rank() {
+        return ShapeFact::open(vec![]);
+    }
+
+    let dims = proto
+        .get_dim()
+        .iter()
+        .enumerate()
+        .map(|(i, d)| match d.get_size() {
+            _ if i as i64 == streamed => DimFact::Streamed,
+            s if s < 0 => DimFact::Any,
+            s => DimFact::Only(s as usize),
+        })
+        .collect();
+
+    if open {
+        ShapeFact::open(dims)
+    } else {
+        ShapeFact::closed(dims)
+    }
+}
+
+/// Returns the facts about each output of a node.
+fn output_facts(analyser: &Analyser, node: usize) -> Vec<TensorFact> {
+    let mut facts = vec![];
+
+    for &j in &analyser.next_edges[node] {
+        let edge = &analyser.edges[j];
+        if facts.len() <= edge.from_out {
+            facts.resize(edge.from_out + 1, TensorFact::new());
+        }
+
+        facts[edge.from_out] = edge.fact.clone();
+    }
+
+    facts
+}
+
+/// Returns the index of the streamed dimension of a fact, if any.
+fn streamed_axis(fact: &TensorFact) -> Result<i64> {
+    let axes: Vec<_> = fact
+        .shape
+        .dims
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.is_streamed())
+        .map(|(i, _)| i as i64)
+        .collect();
+
+    match axes.len() {
+        0 => Ok(-1),
+        1 => Ok(axes[0]),
+        _ => bail!("Can't export a fact with several streamed dimensions {:?}.", axes),
+    }
+}
+
+/// Stores the facts about the outputs of a node as attributes.
+///
+/// The values of the outputs of Const nodes aren't stored again, as they
+/// are already in the `value` attribute of the node.
+fn annotate(node_def: &mut NodeDef, facts: &[TensorFact], is_const: bool) -> Result<()> {
+    let mut shapes = AttrValue_ListValue::new();
+    shapes.set_shape(facts.iter().map(|f| shape_to_pb(&f.shape)).collect());
+
+    let mut types = AttrValue_ListValue::new();
+    types.set_field_type(
+        facts
+            .iter()
+            .map(|f| f.datatype.concretize().unwrap_or(DataType::DT_INVALID))
+            .collect(),
+    );
+
+    let mut open = AttrValue_ListValue::new();
+    open.set_b(facts.iter().map(|f| f.shape.open).collect());
+
+    let mut streamed = AttrValue_ListValue::new();
+    streamed.set_i(facts.iter().map(streamed_axis).collect::<Result<_>>()?);
+
+    let mut lists = vec![
+        (OUTPUT_SHAPES, shapes),
+        (OPEN_SHAPES, open),
+        (OUTPUT_TYPES, types),
+        (STREAMED_AXES, streamed),
+    ];
+
+    if !is_const {
+        let mut values = vec![];
+        let mut ports = vec![];
+        for (port, fact) in facts.iter().enumerate() {
+            if let Some(value) = fact.value.concretize() {
+                values.push(value.to_pb()?);
+                ports.push(port as i64);
+            }
+        }
+
+        if !values.is_empty() {
+            let mut list = AttrValue_ListValue::new();
+            list.set_tensor(RepeatedField::from_vec(values));
+            lists.push((OUTPUT_VALUES, list));
+
+            let mut list = AttrValue_ListValue::new();
+            list.set_i(ports);
+            lists.push((OUTPUT_VALUE_PORTS, list));
+        }
+    }
+
+    for (name, list) in lists {
+        let mut value = AttrValue::new();
+        value.set_list(list);
+        node_def.mut_attr().insert(name.to_string(), value);
+    }
+
+    Ok(())
+}
+
+/// Converts the analysed graph into a TensorFlow GraphDef.
+///
+/// Every node is annotated with the facts inferred about its outputs, so
+/// that `load` can restore them without running the analysis again.
+pub fn to_graph(analyser: &Analyser) -> Result<GraphDef> {
+    let mut graph = tfpb::graph();
+
+    for node in &analyser.nodes {
+        let mut node_def = tfpb::node().name(node.name.clone()).op(node.op_name.clone());
+
+        for &(i, port) in &node.inputs {
+            let name = &analyser.nodes[i].name;
+            node_def = match port {
+                Some(p) if p > 0 => node_def.input(format!("{}:{}", name, p)),
+                _ => node_def.input(name.clone()),
+            };
+        }
+
+        if node.op_name == "Const" {
+            let tensor = node.op.eval(vec![])?.pop().unwrap().into_tensor();
+            node_def = node_def
+                .attr("dtype", tensor.datatype())
+                .attr("value", tensor.to_pb()?);
+        } else {
+            for (name, attr) in node.op.get_attributes() {
+                let value = attr_to_pb(&attr)
+                    .map_err(|e| format!("While exporting node {}: {}", node.name, e))?;
+                node_def.mut_attr().insert(name.to_string(), value);
+            }
+        }
+
+        annotate(&mut node_def, &output_facts(analyser, node.id), node.op_name == "Const")
+            .map_err(|e| format!("While exporting node {}: {}", node.name, e))?;
+        graph = graph.node(node_def);
+    }
+
+    Ok(graph)
+}
+
+/// Saves the analysed graph as a TensorFlow protobuf file.
+pub fn save<P: AsRef<Path>>(analyser: &Analyser, path: P) -> Result<()> {
+    let graph = to_graph(analyser)?;
+    let mut file = File::create(path)?;
+    graph.write_to_writer(&mut file)?;
+
+    Ok(())
+}
+
+impl Analyser {
+    /// Saves the graph and the facts inferred so far as a protobuf file,
+    /// which can be loaded back using `export::load`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        save(self, path)
+    }
+}
+
+/// Reads the facts stored in the attributes of a node, if any.
+fn facts_from_pb(node_def: &NodeDef) -> Result<Option<Vec<TensorFact>>> {
+    let attrs = node_def.get_attr();
+    let list = |name: &str| attrs.get(name).map(|a| a.get_list());
+    let (shapes, types, streamed) = match (list(OUTPUT_SHAPES), list(OUTPUT_TYPES), list(STREAMED_AXES)) {
+        (Some(shapes), Some(types), Some(streamed)) => (shapes.get_shape(), types.get_field_type(), streamed.get_i()),
+        _ => return Ok(None),
+    };
+
+    // Graphs saved before the open marker existed only have closed shapes,
+    // or shapes of unknown rank.
+    let open = list(OPEN_SHAPES).map(|l| l.get_b()).unwrap_or(&[]);
+
+    let mut facts: Vec<_> = shapes
+        .iter()
+        .zip(types)
+        .zip(streamed)
+        .enumerate()
+        .map(|(i, ((shape, &datatype), &streamed))| TensorFact {
+            datatype: match datatype {
+                DataType::DT_INVALID => typefact!(_),
+                dt => typefact!(dt),
+            },
+            shape: shape_from_pb(shape, streamed, open.get(i).cloned().unwrap_or(false)),
+            value: valuefact!(_),
+        })
+        .collect();
+
+    if let (Some(values), Some(ports)) = (list(OUTPUT_VALUES), list(OUTPUT_VALUE_PORTS)) {
+        for (value, &port) in values.get_tensor().iter().zip(ports.get_i()) {
+            let fact = facts
+                .get_mut(port as usize)
+                .ok_or_else(|| format!("Node {} doesn't have an output #{}.", node_def.get_name(), port))?;
+
+            *fact = unify(&tensor_to_fact(Tensor::from_pb(value)?), fact)?;
+        }
+    }
+
+    Ok(Some(facts))
+}
+
+/// Loads a graph saved by `save`, along with the facts that were stored in
+/// it, and returns an analyser whose state is already at a fixed point.
+///
+/// The values of the outputs of Const nodes are recovered from the nodes
+/// themselves, and the other values from the attributes written by `save`.
+pub fn load<P: AsRef<Path>>(path: P, output: usize) -> Result<Analyser> {
+    let mut file = File::open(path)?;
+    let graph: GraphDef = protobuf::parse_from_reader(&mut file)?;
+
+    let model = Model::new(graph.clone())?;
+    let mut analyser = Analyser::new(model, output)?;
+
+    for node_def in graph.get_node() {
+        let facts = match facts_from_pb(node_def)? {
+            Some(facts) => facts,
+            None => continue,
+        };
+
+        let id = analyser
+            .nodes
+            .iter()
+            .position(|n| n.name == node_def.get_name())
+            .ok_or_else(|| format!("There is no node named {}.", node_def.get_name()))?;
+
+        // Const nodes get their value back, so that they are still detected
+        // by the constant folding if the graph is modified again.
+        let value = if analyser.nodes[id].op_name == "Const" {
+            Some(tensor_to_fact(analyser.nodes[id].op.eval(vec![])?.pop().unwrap().into_tensor()))
+        } else {
+            None
+        };
+
+        for j in analyser.next_edges[id].clone() {
+            let edge = &mut analyser.edges[j];
+            if let Some(fact) = facts.get(edge.from_out) {
+                edge.fact = unify(fact, &edge.fact)?;
+            }
+
+            if let Some(ref value) = value {
+                edge.fact = unify(value, &edge.fact)?;
+            }
+        }
+    }
+
+    Ok(analyser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{arr0, ArrayD};
+    use std::collections::HashMap;
+    use tfpb::types::DataType::{DT_FLOAT, DT_INT32};
+    use Node;
+
+    fn constant(name: &str, tensor: Tensor) -> NodeDef {
+        tfpb::node()
+            .name(name.to_string())
+            .op("Const")
+            .attr("dtype", tensor.datatype())
+            .attr("value", tensor.to_pb().unwrap())
+    }
+
+    /// Builds `Add(Split(Identity(3), Conv2D(input, filter)))`, which has a
+    /// node with two outputs.
+    fn graph() -> GraphDef {
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(constant("filter", Tensor::F32(ArrayD::from_elem(vec![1, 1, 2, 4], 0.5))))
+            .node(tfpb::node()
+                .name("conv".to_string())
+                .op("Conv2D")
+                .attr("T", DT_FLOAT)
+                .attr("data_format", "NHWC".to_string())
+                .attr("padding", "VALID".to_string())
+                .attr("strides", vec![1, 1, 1, 1])
+                .input("input".to_string())
+                .input("filter".to_string()))
+            .node(constant("axis", Tensor::I32(arr0(3).into_dyn())))
+            .node(tfpb::node()
+                .name("dim".to_string())
+                .op("Identity")
+                .attr("T", DT_INT32)
+                .input("axis".to_string()))
+            .node(tfpb::node()
+                .name("split".to_string())
+                .op("Split")
+                .attr("T", DT_FLOAT)
+                .attr("num_split", 2)
+                .input("dim".to_string())
+                .input("conv".to_string()))
+            .node(tfpb::node()
+                .name("add".to_string())
+                .op("Add")
+                .attr("T", DT_FLOAT)
+                .input("split".to_string())
+                .input("split:1".to_string()))
+    }
+
+    fn f32s(shape: ShapeFact) -> TensorFact {
+        TensorFact {
+            datatype: typefact!(DT_FLOAT),
+            shape,
+            value: valuefact!(_),
+        }
+    }
+
+    /// Returns the facts of all the edges of the analyser, indexed by the
+    /// names of the nodes they connect and by the output port.
+    fn edge_facts(analyser: &Analyser) -> HashMap<(String, usize, Option<String>), TensorFact> {
+        let name = |node: Option<usize>| node.map(|n| analyser.nodes[n].name.clone());
+
+        analyser
+            .edges
+            .iter()
+            .map(|e| ((name(e.from_node).unwrap(), e.from_out, name(e.to_node)), e.fact.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn save_and_load_facts() {
+        let model = Model::new(graph()).unwrap();
+        let output = model.nodes_by_name["add"];
+        let mut analyser = Analyser::new(model, output).unwrap();
+
+        // Set the facts by hand, so that they cover streamed dimensions,
+        // values of non-Const nodes, open shapes and several outputs.
+        for edge in &mut analyser.edges {
+            let from = &analyser.nodes[edge.from_node.unwrap()];
+            edge.fact = match (from.name.as_str(), edge.from_out) {
+                ("input", _) => f32s(shapefact![1, S, 5, 2]),
+                ("filter", _) => tensor_to_fact(Tensor::F32(ArrayD::from_elem(vec![1, 1, 2, 4], 0.5))),
+                ("conv", _) => f32s(shapefact![1, S, 5, 4]),
+                ("axis", _) | ("dim", _) => tensor_to_fact(Tensor::I32(arr0(3).into_dyn())),
+                ("split", 0) => f32s(shapefact![1, S, 5, 2]),
+                ("split", _) => f32s(shapefact![1, S, _, 2]),
+                _ => f32s(ShapeFact::open(vec![DimFact::Only(1)])),
+            };
+        }
+
+        let path = ::std::env::temp_dir().join("analyser-export-save-and-load.pb");
+        analyser.save(&path).unwrap();
+        let loaded = load(&path, output).unwrap();
+
+        assert_eq!(edge_facts(&loaded), edge_facts(&analyser));
+
+        for node in &analyser.nodes {
+            let other = loaded.nodes.iter().find(|n| n.name == node.name).unwrap();
+            assert_eq!(other.op_name, node.op_name);
+
+            let attributes = |node: &Node| -> HashMap<_, _> {
+                node.op
+                    .get_attributes()
+                    .into_iter()
+                    .map(|(k, v)| (k, attr_to_pb(&v).unwrap()))
+                    .collect()
+            };
+
+            assert_eq!(attributes(other), attributes(node));
+        }
+    }
+}
@@ -0,0 +1,566 @@
+use analyser::interface::path::Path;
+use analyser::interface::solver::Context;
+use analyser::types::{DimFact, Fact, GenericFact, IntFact, ShapeFact, SpecialKind, ValueFact};
+use ndarray::ArrayD;
+use tfpb::types::DataType;
+use Result;
+use Tensor;
+
+use std::fmt;
+
+/// An integer expression which combines two other integer expressions.
+///
+/// The following expressions are supported:
+/// - `sum(a, b)`, which computes `a + b`;
+/// - `mul(a, b)`, which computes `a * b`;
+/// - `div(a, b)`, which computes the integer division of `a` by `b`;
+/// - `quotient(a, b)`, which computes `a / b` knowing that `b` divides `a`;
+/// - `max(a, b)`, which computes the maximum of `a` and `b`.
+///
+/// For instance, the width of the output of a convolution with VALID padding
+/// can be expressed as `sum(div(sum(width, (-1, kernel)), stride), 1)`.
+///
+/// These expressions also work backwards: when the value of the expression
+/// is known along with one of its operands, the other operand is deduced if
+/// it has a unique value. This isn't the case for `div`, as several values
+/// of `a` have the same integer quotient by `b`.
+pub struct ArithmeticExpression {
+    kind: ArithmeticKind,
+    left: Box<Expression<Output = IntFact>>,
+    right: Box<Expression<Output = IntFact>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticKind {
+    Sum,
+    Mul,
+    Div,
+    Quotient,
+    Max,
+}
+
+impl ArithmeticExpression {
+    /// Creates a new ArithmeticExpression instance.
+    pub fn new(
+        kind: ArithmeticKind,
+        left: Box<Expression<Output = IntFact>>,
+        right: Box<Expression<Output = IntFact>>,
+    ) -> ArithmeticExpression {
+        ArithmeticExpression { kind, left, right }
+    }
+
+    /// Computes the value of the expression given the value of its operands.
+    fn compute(&self, left: IntFact, right: IntFact) -> Result<IntFact> {
+        use self::ArithmeticKind::*;
+        use analyser::types::IntFact::*;
+
+        let value = match (self.kind, left, right) {
+            (Div, _, Only(0)) | (Quotient, _, Only(0)) => bail!("Division by zero in {:?}.", self),
+
+            (Sum, Only(a), Only(b)) => Only(a + b),
+            (Mul, Only(a), Only(b)) => Only(a * b),
+            (Div, Only(a), Only(b)) => Only(a / b),
+            (Quotient, Only(a), Only(b)) if a % b == 0 => Only(a / b),
+            (Quotient, Only(a), Only(b)) => bail!("{:?} doesn't divide {:?} in {:?}.", b, a, self),
+            (Max, Only(a), Only(b)) => Only(a.max(b)),
+
+            // Streamed dimensions are unbounded, so they absorb any constant
+            // offset or factor, except a factor of zero.
+            (Mul, Only(0), _) | (Mul, _, Only(0)) => Only(0),
+            (Sum, Special(SpecialKind::Streamed), Only(_))
+            | (Sum, Only(_), Special(SpecialKind::Streamed))
+            | (Mul, Special(SpecialKind::Streamed), Only(_))
+            | (Mul, Only(_), Special(SpecialKind::Streamed))
+            | (Div, Special(SpecialKind::Streamed), Only(_))
+            | (Quotient, Special(SpecialKind::Streamed), Only(_))
+            | (Max, Special(SpecialKind::Streamed), Only(_))
+            | (Max, Only(_), Special(SpecialKind::Streamed)) => Special(SpecialKind::Streamed),
+
+            _ => Any,
+        };
+
+        Ok(value)
+    }
+
+    /// Computes the value of one operand given the value of the expression
+    /// and the value of the other operand, if it's unique.
+    ///
+    /// The `left` flag tells whether the unknown operand is the left one.
+    fn solve(&self, value: isize, known: isize, left: bool) -> Result<Option<isize>> {
+        use self::ArithmeticKind::*;
+
+        let solution = match (self.kind, left) {
+            (Sum, _) => Some(value - known),
+
+            (Mul, _) if known == 0 => None,
+            (Mul, _) if value % known == 0 => Some(value / known),
+            (Mul, _) => bail!("{:?} doesn't divide {:?} in {:?}.", known, value, self),
+
+            // If a / b = v, then a = v * b and b = a / v.
+            (Quotient, true) => Some(value * known),
+            (Quotient, false) if value == 0 => None,
+            (Quotient, false) if known % value == 0 => Some(known / value),
+            (Quotient, false) => bail!("{:?} doesn't divide {:?} in {:?}.", value, known, self),
+
+            // If max(a, b) = v and b < v, then a = v.
+            (Max, _) if known > value => bail!("{:?} is greater than {:?} in {:?}.", known, value, self),
+            (Max, _) if known < value => Some(value),
+            (Max, _) => None,
+
+            (Div, _) => None,
+        };
+
+        Ok(solution)
+    }
+}
+
+impl Expression for ArithmeticExpression {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        self.compute(self.left.get(context)?, self.right.get(context)?)
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    ///
+    /// If one of the operands is unknown and the value of the expression is
+    /// known, this tries to deduce the value of that operand. Otherwise, it
+    /// only checks that the value is compatible with the operands.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        let left = self.left.get(context)?;
+        let right = self.right.get(context)?;
+
+        match (value, left, right) {
+            (IntFact::Only(v), IntFact::Any, IntFact::Only(k)) => {
+                if let Some(solution) = self.solve(v, k, true)? {
+                    self.left.set(context, IntFact::Only(solution))?;
+                }
+            }
+
+            (IntFact::Only(v), IntFact::Only(k), IntFact::Any) => {
+                if let Some(solution) = self.solve(v, k, false)? {
+                    self.right.set(context, IntFact::Only(solution))?;
+                }
+            }
+
+            // Streamed values can only come from another streamed value.
+            (IntFact::Special(SpecialKind::Streamed), IntFact::Any, IntFact::Only(_))
+                if self.kind != ArithmeticKind::Max =>
+            {
+                self.left.set(context, value)?;
+            }
+
+            (IntFact::Special(SpecialKind::Streamed), IntFact::Only(_), IntFact::Any)
+                if self.kind == ArithmeticKind::Sum || self.kind == ArithmeticKind::Mul =>
+            {
+                self.right.set(context, value)?;
+            }
+
+            _ => (),
+        }
+
+        self.get(context)?.unify(&value)?;
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        let mut paths = self.left.get_paths();
+        paths.extend(self.right.get_paths());
+        paths
+    }
+}
+
+impl fmt::Debug for ArithmeticExpression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self.kind {
+            ArithmeticKind::Sum => "+",
+            ArithmeticKind::Mul => "*",
+            ArithmeticKind::Div => "/",
+            ArithmeticKind::Quotient => "/!",
+            ArithmeticKind::Max => return write!(formatter, "max({:?}, {:?})", self.left, self.right),
+        };
+
+        write!(formatter, "({:?} {} {:?})", self.left, symbol, self.right)
+    }
+}
+
+/// Builds an expression which computes the sum of two integer expressions.
+pub fn sum<EA, EB, A, B>(left: A, right: B) -> ArithmeticExpression
+where
+    EA: Expression<Output = IntFact> + 'static,
+    EB: Expression<Output = IntFact> + 'static,
+    A: IntoExpression<EA>,
+    B: IntoExpression<EB>,
+{
+    ArithmeticExpression::new(ArithmeticKind::Sum, Box::new(left.into_expr()), Box::new(right.into_expr()))
+}
+
+/// Builds an expression which computes the product of two integer expressions.
+pub fn mul<EA, EB, A, B>(left: A, right: B) -> ArithmeticExpression
+where
+    EA: Expression<Output = IntFact> + 'static,
+    EB: Expression<Output = IntFact> + 'static,
+    A: IntoExpression<EA>,
+    B: IntoExpression<EB>,
+{
+    ArithmeticExpression::new(ArithmeticKind::Mul, Box::new(left.into_expr()), Box::new(right.into_expr()))
+}
+
+/// Builds an expression which computes the integer division of two integer
+/// expressions, rounding towards zero.
+pub fn div<EA, EB, A, B>(left: A, right: B) -> ArithmeticExpression
+where
+    EA: Expression<Output = IntFact> + 'static,
+    EB: Expression<Output = IntFact> + 'static,
+    A: IntoExpression<EA>,
+    B: IntoExpression<EB>,
+{
+    ArithmeticExpression::new(ArithmeticKind::Div, Box::new(left.into_expr()), Box::new(right.into_expr()))
+}
+
+/// Builds an expression which computes the exact quotient of two integer
+/// expressions, knowing that the second one divides the first one.
+pub fn quotient<EA, EB, A, B>(left: A, right: B) -> ArithmeticExpression
+where
+    EA: Expression<Output = IntFact> + 'static,
+    EB: Expression<Output = IntFact> + 'static,
+    A: IntoExpression<EA>,
+    B: IntoExpression<EB>,
+{
+    ArithmeticExpression::new(ArithmeticKind::Quotient, Box::new(left.into_expr()), Box::new(right.into_expr()))
+}
+
+/// Builds an expression which computes the maximum of two integer expressions.
+pub fn max<EA, EB, A, B>(left: A, right: B) -> ArithmeticExpression
+where
+    EA: Expression<Output = IntFact> + 'static,
+    EB: Expression<Output = IntFact> + 'static,
+    A: IntoExpression<EA>,
+    B: IntoExpression<EB>,
+{
+    ArithmeticExpression::new(ArithmeticKind::Max, Box::new(left.into_expr()), Box::new(right.into_expr()))
+}
+
+impl IntoExpression<ArithmeticExpression> for ArithmeticExpression {
+    /// Converts the value to an Expression.
+    fn into_expr(self) -> ArithmeticExpression {
+        self
+    }
+}
+
+/// Computes the product of several integer facts, or of the known ones if
+/// `skip` is the index of an unknown one.
+fn product_of_facts(facts: &[IntFact], skip: Option<usize>) -> IntFact {
+    let mut product = IntFact::Only(1);
+
+    for (i, fact) in facts.iter().enumerate() {
+        if Some(i) == skip {
+            continue;
+        }
+
+        product = match (product, fact) {
+            (IntFact::Only(0), _) | (_, IntFact::Only(0)) => IntFact::Only(0),
+            (IntFact::Only(a), IntFact::Only(b)) => IntFact::Only(a * b),
+            (IntFact::Any, _) | (_, IntFact::Any) => IntFact::Any,
+            _ => IntFact::Special(SpecialKind::Streamed),
+        };
+    }
+
+    product
+}
+
+/// Deduces the value of the only unknown factor of a product, if any.
+///
+/// Returns None if there are several unknown factors, or if the value of the
+/// unknown factor can't be determined (e.g. when the product is zero).
+fn solve_product(product: IntFact, facts: &[IntFact]) -> Result<Option<(usize, IntFact)>> {
+    let unknown: Vec<_> = (0..facts.len()).filter(|&i| facts[i] == IntFact::Any).collect();
+    if unknown.len() != 1 {
+        return Ok(None);
+    }
+
+    let others = product_of_facts(facts, Some(unknown[0]));
+    let solution = match (product, others) {
+        (_, IntFact::Only(0)) => None,
+        (IntFact::Only(p), IntFact::Only(o)) if p % o == 0 => Some(IntFact::Only(p / o)),
+        (IntFact::Only(p), IntFact::Only(o)) => bail!("{:?} doesn't divide {:?}.", o, p),
+        (IntFact::Special(SpecialKind::Streamed), IntFact::Only(_)) => Some(product),
+        _ => None,
+    };
+
+    Ok(solution.map(|s| (unknown[0], s)))
+}
+
+/// An integer expression which computes the product of several integer
+/// expressions, and which can deduce the value of one unknown factor from
+/// the value of the product.
+pub struct ProductExpression {
+    items: Vec<Box<Expression<Output = IntFact>>>,
+}
+
+impl Expression for ProductExpression {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        let facts = self.items.iter().map(|i| i.get(context)).collect::<Result<Vec<_>>>()?;
+        Ok(product_of_facts(&facts, None))
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        let facts = self.items.iter().map(|i| i.get(context)).collect::<Result<Vec<_>>>()?;
+
+        if let Some((i, solution)) = solve_product(value, &facts)? {
+            self.items[i].set(context, solution)?;
+        }
+
+        self.get(context)?.unify(&value)?;
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        self.items.iter().flat_map(|e| e.get_paths()).collect()
+    }
+}
+
+impl fmt::Debug for ProductExpression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "product({:?})", self.items)
+    }
+}
+
+/// Builds an expression which computes the product of integer expressions.
+///
+/// For instance, one could write:
+/// ```text
+/// solver.equals(&output.shape[0], product(wrap![&input.shape[0], &input.shape[1]]));
+/// ```
+pub fn product(items: Vec<Box<Expression<Output = IntFact>>>) -> ProductExpression {
+    ProductExpression { items }
+}
+
+impl IntoExpression<ProductExpression> for ProductExpression {
+    /// Converts the value to an Expression.
+    fn into_expr(self) -> ProductExpression {
+        self
+    }
+}
+
+/// An integer expression which computes the number of elements of a shape,
+/// i.e. the product of all its dimensions.
+pub struct ShapeProductExpression {
+    shape: Box<Expression<Output = ShapeFact>>,
+}
+
+impl ShapeProductExpression {
+    /// Returns the dimensions of a shape as integer facts, if it's closed.
+    fn dims(shape: &ShapeFact) -> Option<Vec<IntFact>> {
+        if shape.open {
+            return None;
+        }
+
+        let dims = shape
+            .dims
+            .iter()
+            .map(|d| match d {
+                DimFact::Any => IntFact::Any,
+                DimFact::Streamed => IntFact::Special(SpecialKind::Streamed),
+                DimFact::Only(i) => IntFact::Only(*i as isize),
+            })
+            .collect();
+
+        Some(dims)
+    }
+}
+
+impl Expression for ShapeProductExpression {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        match ShapeProductExpression::dims(&self.shape.get(context)?) {
+            Some(dims) => Ok(product_of_facts(&dims, None)),
+            None => Ok(IntFact::Any),
+        }
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        let mut shape = self.shape.get(context)?;
+        let dims = match ShapeProductExpression::dims(&shape) {
+            Some(dims) => dims,
+            None => return Ok(()),
+        };
+
+        if let Some((i, solution)) = solve_product(value, &dims)? {
+            shape.dims[i] = match solution {
+                IntFact::Only(d) if d >= 0 => DimFact::Only(d as usize),
+                IntFact::Special(SpecialKind::Streamed) => DimFact::Streamed,
+                _ => bail!("Invalid dimension {:?} deduced for {:?}.", solution, self),
+            };
+
+            self.shape.set(context, shape)?;
+        }
+
+        self.get(context)?.unify(&value)?;
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        self.shape.get_paths()
+    }
+}
+
+impl fmt::Debug for ShapeProductExpression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "product({:?})", self.shape)
+    }
+}
+
+/// Builds an expression which computes the number of elements of a shape.
+///
+/// For instance, one could write:
+/// ```text
+/// solver.equals(product_of(&input.shape), product_of(&output.shape));
+/// ```
+pub fn product_of<E, A>(shape: A) -> ShapeProductExpression
+where
+    E: Expression<Output = ShapeFact> + 'static,
+    A: IntoExpression<E>,
+{
+    ShapeProductExpression { shape: Box::new(shape.into_expr()) }
+}
+
+impl IntoExpression<ShapeProductExpression> for ShapeProductExpression {
+    /// Converts the value to an Expression.
+    fn into_expr(self) -> ShapeProductExpression {
+        self
+    }
+}
+
+/// Maximum number of elements of the tensors built by `tensor_of`.
+///
+/// Tensors built this way are meant to hold shapes or indices, so we refuse
+/// to build larger ones rather than keeping huge vectors of expressions.
+pub const MAX_CONSTRUCTED_ELEMENTS: usize = 1024;
+
+/// A value expression which builds an integer tensor of known datatype and
+/// shape from one integer expression per element, in row-major order.
+///
+/// It works in both directions: the value is known once all the elements are
+/// known, and setting a known value sets the value of all the elements.
+pub struct TensorExpression {
+    datatype: DataType,
+    shape: Vec<usize>,
+    elements: Vec<Box<Expression<Output = IntFact>>>,
+}
+
+impl TensorExpression {
+    /// Builds a tensor from the values of its elements.
+    fn build(&self, values: Vec<isize>) -> Result<Tensor> {
+        let tensor = match self.datatype {
+            DataType::DT_INT32 => {
+                let values = values.into_iter().map(|v| v as i32).collect();
+                Tensor::I32(ArrayD::from_shape_vec(&self.shape[..], values)?)
+            }
+            _ => bail!("Can't build a tensor of type {:?} in {:?}.", self.datatype, self),
+        };
+
+        Ok(tensor)
+    }
+
+    /// Returns the elements of a tensor in row-major order.
+    fn elements_of(&self, tensor: &Tensor) -> Result<Vec<isize>> {
+        if tensor.shape() != &self.shape[..] {
+            bail!("Expected a tensor of shape {:?} in {:?}, found {:?}.", self.shape, self, tensor);
+        }
+
+        match tensor {
+            Tensor::I32(array) => Ok(array.iter().map(|&v| v as isize).collect()),
+            _ => bail!("Expected a tensor of type {:?} in {:?}, found {:?}.", self.datatype, self, tensor),
+        }
+    }
+}
+
+impl Expression for TensorExpression {
+    type Output = ValueFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<ValueFact> {
+        let mut values = Vec::with_capacity(self.elements.len());
+
+        for element in &self.elements {
+            match element.get(context)? {
+                IntFact::Only(v) => values.push(v),
+                _ => return Ok(GenericFact::Any),
+            }
+        }
+
+        Ok(GenericFact::Only(self.build(values)?))
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: ValueFact) -> Result<()> {
+        if let Some(tensor) = value.concretize() {
+            let values = self.elements_of(&tensor)?;
+
+            for (element, v) in self.elements.iter().zip(values) {
+                element.set(context, IntFact::Only(v))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        self.elements.iter().flat_map(|e| e.get_paths()).collect()
+    }
+}
+
+impl fmt::Debug for TensorExpression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "tensor_of({:?}, {:?}, {:?})", self.datatype, self.shape, self.elements)
+    }
+}
+
+/// Builds a value expression for an integer tensor of the given datatype and
+/// shape, whose elements are given in row-major order.
+///
+/// This lets the solver compute the value of small integer tensors, e.g. the
+/// output of a Gather with constant indices, as soon as the elements that
+/// they are made of are known. For instance, one could write:
+/// ```text
+/// solver.equals(&output.value, tensor_of(DataType::DT_INT32, vec![2], wrap![
+///     &params.value[1],
+///     &input.shape[0],
+/// ]));
+/// ```
+pub fn tensor_of(
+    datatype: DataType,
+    shape: Vec<usize>,
+    elements: Vec<Box<Expression<Output = IntFact>>>,
+) -> Result<TensorExpression> {
+    if elements.len() != shape.iter().product::<usize>() {
+        bail!("Expected {:?} elements for shape {:?}, found {:?}.",
+            shape.iter().product::<usize>(), shape, elements.len());
+    }
+
+    if elements.len() > MAX_CONSTRUCTED_ELEMENTS {
+        bail!("Refusing to build a tensor of {:?} elements.", elements.len());
+    }
+
+    Ok(TensorExpression { datatype, shape, elements })
+}
+
+impl IntoExpression<TensorExpression> for TensorExpression {
+    /// Converts the value to an Expression.
+    fn into_expr(self) -> TensorExpression {
+        self
+    }
+}
@@ -0,0 +1,422 @@
+use super::constants::build_const_node;
+use super::prelude::*;
+use super::Result;
+use ndarray::prelude::*;
+use ops::nn::local_patch::DataFormat;
+use ops::{Attr, OpBuilder};
+use tfpb;
+use tfpb::types::DataType;
+use Model;
+use Node;
+use Tensor;
+
+/// The default value of the `epsilon` attribute of FusedBatchNorm.
+const DEFAULT_EPSILON: f32 = 0.0001;
+
+/// A constant affine transformation `x * scale + shift`, where `scale` and
+/// `shift` hold one value per output channel of the convolution.
+struct Affine {
+    scale: Array1<f32>,
+    shift: Array1<f32>,
+}
+
+/// Returns the value of a given input of a node, if the analyser proved
+/// that it is a constant f32 tensor.
+fn const_input(analyser: &Analyser, node: usize, port: usize) -> Option<ArrayD<f32>> {
+    let edge = analyser.prev_edges[node].get(port)?;
+
+    match analyser.edges[*edge].fact.value.concretize() {
+        Some(Tensor::F32(array)) => Some(array),
+        _ => None,
+    }
+}
+
+/// Returns the value of a given input of a node as one value per channel,
+/// if it is constant and can be broadcasted along the last axis.
+fn const_channels(analyser: &Analyser, node: usize, port: usize, channels: usize) -> Option<Array1<f32>> {
+    let array = const_input(analyser, node, port)?;
+
+    // We only accept scalars, or tensors whose only non-trivial axis is the
+    // last one, otherwise the operation would broadcast the convolution.
+    if array.shape().iter().rev().skip(1).any(|&d| d != 1) {
+        return None;
+    }
+
+    let len = array.len();
+    let array = array.into_shape(len).ok()?;
+    array.broadcast(channels).map(|a| a.to_owned())
+}
+
+/// Returns the only successor of a node and the port through which they are
+/// connected, if the first output of the node has exactly one consumer.
+fn single_successor(analyser: &Analyser, node: usize) -> Option<(usize, usize)> {
+    let next = &analyser.next_edges[node];
+    if next.len() != 1 || analyser.edges[next[0]].from_out != 0 {
+        return None;
+    }
+
+    let target = analyser.edges[next[0]].to_node?;
+    let port = analyser.prev_edges[target].iter().position(|&j| j == next[0])?;
+
+    Some((target, port))
+}
+
+/// Tries to absorb a node of the chain into the affine transformation, given
+/// the port through which the chain enters the node.
+fn absorb(analyser: &Analyser, node: usize, port: usize, affine: &mut Affine) -> bool {
+    let channels = affine.scale.len();
+    let other = 1 - port.min(1);
+    let node = &analyser.nodes[node];
+
+    match node.op_name.as_str() {
+        "Mul" => match const_channels(analyser, node.id, other, channels) {
+            Some(c) => {
+                affine.scale *= &c;
+                affine.shift *= &c;
+                true
+            }
+            None => false,
+        },
+
+        "Add" | "BiasAdd" => match const_channels(analyser, node.id, other, channels) {
+            Some(c) if node.op_name == "Add" || port == 0 => {
+                affine.shift += &c;
+                true
+            }
+            _ => false,
+        },
+
+        "Sub" if port == 0 => match const_channels(analyser, node.id, 1, channels) {
+            Some(c) => {
+                affine.shift -= &c;
+                true
+            }
+            None => false,
+        },
+
+        "FusedBatchNorm" if port == 0 => {
+            let params: Option<Vec<_>> = (1..5)
+                .map(|i| const_channels(analyser, node.id, i, channels))
+                .collect();
+
+            let (gamma, beta, mean, variance) = match params {
+                Some(ref p) => (&p[0], &p[1], &p[2], &p[3]),
+                None => return false,
+            };
+
+            // In training mode, which is the default, the batch statistics are
+            // used instead of the stored mean and variance.
+            let attributes = node.op.get_attributes();
+            match attributes.get("is_training") {
+                Some(Attr::Bool(false)) => (),
+                _ => return false,
+            }
+
+            let epsilon = match attributes.get("epsilon") {
+                Some(Attr::Float(e)) => *e,
+                _ => DEFAULT_EPSILON,
+            };
+
+            let factor = gamma / &variance.mapv(|v| (v + epsilon).sqrt());
+            affine.scale *= &factor;
+            affine.shift = &affine.shift * &factor + beta - &(mean * &factor);
+            true
+        }
+
+        _ => false,
+    }
+}
+
+/// Creates a new BiasAdd node for f32 tensors.
+fn build_bias_add_node(id: usize, name: String) -> Result<Node> {
+    let node_def = tfpb::node()
+        .name(name.clone())
+        .op("BiasAdd")
+        .attr("T", DataType::DT_FLOAT);
+
+    Ok(Node {
+        id,
+        name,
+        op_name: "BiasAdd".to_string(),
+        inputs: vec![],
+        op: OpBuilder::new().build(&node_def)?,
+    })
+}
+
+/// Folds the chain of constant scales and shifts following a convolution,
+/// and returns whether the graph was modified.
+fn fold_chain(analyser: &mut Analyser, conv: usize) -> Result<bool> {
+    // The scales and shifts are applied along the last axis, which only holds
+    // the channels in NHWC format, which is also the default.
+    match analyser.nodes[conv].op.get_attributes().get("data_format") {
+        None | Some(Attr::DataFormat(DataFormat::NHWC)) => (),
+        _ => return Ok(false),
+    }
+
+    let filter = match const_input(analyser, conv, 1) {
+        Some(ref f) if f.ndim() == 4 => f.clone(),
+        _ => return Ok(false),
+    };
+
+    // Conv2D filters have shape [height, width, in_channels, out_channels].
+    let channels = filter.shape()[3];
+    let mut affine = Affine {
+        scale: Array1::ones(channels),
+        shift: Array1::zeros(channels),
+    };
+
+    // Find the longest chain of scales and shifts after the convolution,
+    // making sure that nobody else consumes the intermediate values, and
+    // that every node of the chain can be bypassed.
+    let mut chain = vec![];
+    let mut last = conv;
+
+    while let Some((node, port)) = single_successor(analyser, last) {
+        if !analyser.can_bypass(node, port) || !absorb(analyser, node, port, &mut affine) {
+            break;
+        }
+
+        chain.push((node, port));
+        last = node;
+    }
+
+    if chain.is_empty() {
+        return Ok(false);
+    }
+
+    let conv_name = analyser.nodes[conv].name.clone();
+    debug!("Folding {:?} into the filter of {}.", chain, conv_name);
+
+    // Build the new tensors before touching the graph, so that nothing can
+    // fail once the rewrite has started.
+    let filter = Tensor::F32(&filter * &affine.scale.into_dyn());
+    let filter_pb = filter.to_pb()?;
+
+    let bias = if affine.shift.iter().any(|&s| s != 0.) {
+        let bias = Tensor::F32(affine.shift.into_dyn());
+        let bias_pb = bias.to_pb()?;
+        let add_name = format!("{}/folded_bias_add", conv_name);
+        let add_node = build_bias_add_node(analyser.nodes.len() + 2, add_name)?;
+        Some((bias, bias_pb, add_node))
+    } else {
+        None
+    };
+
+    // Replace the filter with the scaled one.
+    let filter_id = analyser.nodes.len();
+    let filter_name = format!("{}/folded_filter", conv_name);
+    let filter_node = build_const_node(filter_id, filter_name, filter_pb);
+    analyser.add_node(filter_node);
+
+    let filter_edge = analyser.prev_edges[conv][1];
+    if let Some(old) = analyser.edges[filter_edge].from_node {
+        analyser.next_edges[old].retain(|&k| k != filter_edge);
+    }
+
+    analyser.edges[filter_edge].from_node = Some(filter_id);
+    analyser.edges[filter_edge].from_out = 0;
+    analyser.edges[filter_edge].fact = tensor_to_fact(filter);
+    analyser.next_edges[filter_id].push(filter_edge);
+    analyser.nodes[conv].inputs[1] = (filter_id, None);
+
+    // Remove the nodes of the chain, which leaves the convolution connected
+    // to all the successors of the last node of the chain. This can't fail,
+    // as `can_bypass` was checked while building the chain.
+    for (node, port) in chain {
+        analyser.bypass(node, port)?;
+    }
+
+    // Insert a single BiasAdd after the convolution if needed.
+    if let Some((bias, bias_pb, add_node)) = bias {
+        let bias_id = analyser.nodes.len();
+        let bias_name = format!("{}/folded_bias", conv_name);
+        let bias_node = build_const_node(bias_id, bias_name, bias_pb);
+        analyser.add_node(bias_node);
+
+        let add_id = analyser.add_node(add_node);
+
+        let successors: Vec<_> = analyser.next_edges[conv].drain(..).collect();
+        for j in successors {
+            analyser.edges[j].from_node = Some(add_id);
+
+            if let Some(target) = analyser.edges[j].to_node {
+                analyser.nodes[target]
+                    .inputs
+                    .iter_mut()
+                    .filter(|i| i.0 == conv)
+                    .for_each(|i| *i = (add_id, None));
+            }
+
+            analyser.next_edges[add_id].push(j);
+        }
+
+        if analyser.output == conv {
+            analyser.output = add_id;
+        }
+
+        analyser.connect(conv, 0, add_id, TensorFact::new());
+        analyser.connect(bias_id, 0, add_id, tensor_to_fact(bias));
+    }
+
+    Ok(true)
+}
+
+/// Folds the constant scales and shifts which follow convolutions into the
+/// filters of these convolutions, and returns the number of convolutions
+/// which were rewritten.
+///
+/// Models are often exported with a FusedBatchNorm, or with Mul and Add nodes
+/// whose second operand is constant, right after each Conv2D node. Because a
+/// convolution is linear in its filter, we can compute `conv(x, f) * a + b`
+/// as `conv(x, f * a) + b` instead, which saves a pass over the output and
+/// merges all the shifts into a single BiasAdd.
+///
+/// Only the chains whose intermediate values have no other consumer, and in
+/// which the scales and shifts hold at most one value per output channel, are
+/// folded. The folded nodes are only removed by `prune_unused`.
+pub fn fold_scale_and_shift(analyser: &mut Analyser) -> Result<usize> {
+    let convs: Vec<_> = analyser
+        .nodes
+        .iter()
+        .filter(|n| n.op_name == "Conv2D")
+        .map(|n| n.id)
+        .collect();
+
+    let mut folded = 0;
+    for conv in convs {
+        if fold_chain(analyser, conv)? {
+            folded += 1;
+        }
+    }
+
+    info!("Folded scales and shifts into {:?} convolutions.", folded);
+    analyser.reset_plan()?;
+
+    Ok(folded)
+}
+
+impl Analyser {
+    /// Folds the constant scales and shifts which follow convolutions into
+    /// their filters, and returns the number of convolutions rewritten.
+    pub fn fold_scale_and_shift(&mut self) -> Result<usize> {
+        fold_scale_and_shift(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::DT_FLOAT;
+
+    fn constant(name: &str, array: ArrayD<f32>) -> NodeDef {
+        tfpb::node()
+            .name(name.to_string())
+            .op("Const")
+            .attr("dtype", DT_FLOAT)
+            .attr("value", Tensor::F32(array).to_pb().unwrap())
+    }
+
+    fn f32_node(name: &str, op: &str, inputs: &[&str]) -> NodeDef {
+        inputs
+            .iter()
+            .fold(tfpb::node().name(name.to_string()).op(op).attr("T", DT_FLOAT), |node, i| {
+                node.input(i.to_string())
+            })
+    }
+
+    /// Builds `FusedBatchNorm(Conv2D(input, filter) * scale + shift)`.
+    fn graph() -> tfpb::graph::GraphDef {
+        let channels = |values: &[f32]| arr1(values).into_dyn();
+
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(constant("filter", ArrayD::from_shape_fn(vec![3, 3, 2, 4], |_| ::rand::random::<f32>())))
+            .node(f32_node("conv", "Conv2D", &["input", "filter"])
+                .attr("padding", "VALID".to_string())
+                .attr("strides", vec![1, 1, 1, 1]))
+            .node(constant("scale", channels(&[1., 2., -1., 0.5])))
+            .node(f32_node("mul", "Mul", &["conv", "scale"]))
+            .node(constant("shift", channels(&[0., 1., 2., -3.])))
+            .node(f32_node("add", "Add", &["mul", "shift"]))
+            .node(constant("gamma", channels(&[1., 0.5, 2., 1.])))
+            .node(constant("beta", channels(&[0.1, 0.2, 0.3, 0.4])))
+            .node(constant("mean", channels(&[0., 1., -1., 2.])))
+            .node(constant("variance", channels(&[1., 4., 0.25, 2.])))
+            .node(f32_node("bn", "FusedBatchNorm", &["add", "gamma", "beta", "mean", "variance"])
+                .attr("is_training", false)
+                .attr("epsilon", 0.001f32))
+    }
+
+    fn eval(model: &Model, output: &str, value: &Tensor) -> Tensor {
+        let input = model.nodes_by_name["input"];
+        let output = model.nodes_by_name[output];
+        model.run(vec![(input, value.clone())], output).unwrap().remove(0)
+    }
+
+    #[test]
+    fn fold_conv_mul_add_batch_norm() {
+        let before = Model::new(graph()).unwrap();
+        let output = before.nodes_by_name["bn"];
+
+        let mut analyser = Analyser::new(Model::new(graph()).unwrap(), output).unwrap();
+        analyser.run().unwrap();
+
+        assert_eq!(analyser.fold_scale_and_shift().unwrap(), 1);
+        analyser.prune_unused();
+
+        let folded = analyser.nodes[analyser.output].name.clone();
+        assert_eq!(folded, "conv/folded_bias_add");
+
+        let after = analyser.into_model();
+        assert!(!after.nodes_by_name.contains_key("mul"));
+        assert!(!after.nodes_by_name.contains_key("bn"));
+
+        for _ in 0..10 {
+            let input = Tensor::F32(ArrayD::from_shape_fn(vec![1, 5, 5, 2], |_| ::rand::random::<f32>() * 2. - 1.));
+            assert!(eval(&before, "bn", &input).close_enough(&eval(&after, &folded, &input)));
+        }
+    }
+
+    #[test]
+    fn keep_batch_norm_in_training_mode() {
+        let mut graph = graph();
+        graph.mut_node().last_mut().unwrap().mut_attr().remove("is_training");
+
+        let model = Model::new(graph).unwrap();
+        let output = model.nodes_by_name["bn"];
+
+        let mut analyser = Analyser::new(model, output).unwrap();
+        analyser.run().unwrap();
+        analyser.fold_scale_and_shift().unwrap();
+        analyser.prune_unused();
+
+        let model = analyser.into_model();
+        assert!(model.nodes_by_name.contains_key("bn"));
+        assert!(!model.nodes_by_name.contains_key("mul"));
+    }
+
+    #[test]
+    fn keep_chain_after_nchw_convolution() {
+        let mut graph = graph();
+        graph.mut_node()[2] = f32_node("conv", "Conv2D", &["input", "filter"])
+            .attr("data_format", "NCHW".to_string())
+            .attr("padding", "VALID".to_string())
+            .attr("strides", vec![1, 1, 1, 1]);
+
+        let model = Model::new(graph).unwrap();
+        let output = model.nodes_by_name["bn"];
+
+        // The analysis fails on NCHW convolutions, so only the constants are
+        // made known to the analyser.
+        let mut analyser = Analyser::new(model, output).unwrap();
+        for node in 0..analyser.nodes.len() {
+            if analyser.nodes[node].op_name == "Const" {
+                let value = analyser.nodes[node].op.eval(vec![]).unwrap().pop().unwrap().into_tensor();
+                analyser.hint(node, &tensor_to_fact(value)).unwrap();
+            }
+        }
+
+        assert_eq!(analyser.fold_scale_and_shift().unwrap(), 0);
+    }
+}
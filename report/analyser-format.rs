@@ -0,0 +1,368 @@
+use analyser::types::{DimFact, Fact, GenericFact, ShapeFact, TensorFact, TypeFact};
+use ndarray::ArrayD;
+use protobuf::ProtobufEnum;
+use std::fmt;
+use std::str::FromStr;
+use tfpb::types::DataType;
+use {Error, Result, Tensor};
+
+/// Returns the name of a datatype in the text format of facts, which is
+/// the name of the protobuf enum for the datatypes without a short name.
+fn datatype_to_str(datatype: DataType) -> String {
+    let name = match datatype {
+        DataType::DT_FLOAT => "f32",
+        DataType::DT_DOUBLE => "f64",
+        DataType::DT_INT32 => "i32",
+        DataType::DT_INT8 => "i8",
+        DataType::DT_UINT8 => "u8",
+        DataType::DT_STRING => "string",
+        _ => return format!("{:?}", datatype),
+    };
+
+    name.to_string()
+}
+
+/// Returns the datatype with the given name in the text format of facts.
+fn datatype_from_str(name: &str) -> Result<DataType> {
+    let datatype = match name {
+        "f32" => DataType::DT_FLOAT,
+        "f64" => DataType::DT_DOUBLE,
+        "i32" => DataType::DT_INT32,
+        "i8" => DataType::DT_INT8,
+        "u8" => DataType::DT_UINT8,
+        "string" => DataType::DT_STRING,
+        _ => match DataType::values().iter().find(|dt| format!("{:?}", dt) == name) {
+            Some(&dt) => dt,
+            None => bail!("Unknown datatype {:?}.", name),
+        },
+    };
+
+    Ok(datatype)
+}
+
+/// Writes the elements of an array separated by commas.
+fn write_elements<T: fmt::Debug>(f: &mut fmt::Formatter, array: &ArrayD<T>) -> fmt::Result {
+    for (i, x) in array.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+
+        write!(f, "{:?}", x)?;
+    }
+
+    Ok(())
+}
+
+/// Parses comma-separated elements into an array of the given shape.
+fn parse_elements<T: FromStr>(shape: Vec<usize>, elements: &str) -> Result<ArrayD<T>> {
+    // The elements of an empty tensor are written as an empty list.
+    let values = if elements.trim().is_empty() {
+        vec![]
+    } else {
+        elements
+            .split(',')
+            .map(|e| match e.trim().parse() {
+                Ok(v) => Ok(v),
+                Err(_) => bail!("Invalid tensor element {:?}.", e),
+            })
+            .collect::<Result<Vec<T>>>()?
+    };
+
+    Ok(ArrayD::from_shape_vec(shape, values).map_err(|e| format!("{}", e))?)
+}
+
+impl fmt::Display for DimFact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DimFact::Any => write!(f, "_"),
+            DimFact::Streamed => write!(f, "S"),
+            DimFact::Only(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+/// Parses a dimension, i.e. `_`, `S` or an integer.
+impl FromStr for DimFact {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DimFact> {
+        match s.trim() {
+            "_" => Ok(DimFact::Any),
+            "S" => Ok(DimFact::Streamed),
+            d => match d.parse() {
+                Ok(i) => Ok(DimFact::Only(i)),
+                Err(_) => bail!("Invalid dimension {:?}.", d),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ShapeFact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, dim) in self.dims.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{}", dim)?;
+        }
+
+        match (self.open, self.dims.is_empty()) {
+            (true, true) => write!(f, "..]"),
+            (true, false) => write!(f, ",..]"),
+            (false, _) => write!(f, "]"),
+        }
+    }
+}
+
+/// Parses a shape with the notation of `shapefact!`, e.g. `[S,40,_,..]`.
+impl FromStr for ShapeFact {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ShapeFact> {
+        let s = s.trim();
+        if !s.starts_with('[') || !s.ends_with(']') {
+            bail!("Shape {:?} should be enclosed in brackets.", s);
+        }
+
+        let inner = s[1..s.len() - 1].trim();
+        let mut items: Vec<_> = if inner.is_empty() {
+            vec![]
+        } else {
+            inner.split(',').map(|i| i.trim()).collect()
+        };
+
+        let open = items.last() == Some(&"..");
+        if open {
+            items.pop();
+        }
+
+        let dims = items
+            .into_iter()
+            .map(|d| d.parse())
+            .collect::<Result<_>>()?;
+
+        Ok(ShapeFact { open, dims })
+    }
+}
+
+/// Writes a datatype using its short name, e.g. `f32`, or the name of the
+/// protobuf enum, e.g. `DT_INT64`, for the datatypes which have none.
+impl fmt::Display for TypeFact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenericFact::Any => write!(f, "_"),
+            GenericFact::Only(dt) => write!(f, "{}", datatype_to_str(*dt)),
+        }
+    }
+}
+
+/// Parses a datatype, i.e. `_`, a short name such as `f32` or the name of
+/// the protobuf enum such as `DT_INT64`.
+impl FromStr for TypeFact {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TypeFact> {
+        match s.trim() {
+            "_" => Ok(GenericFact::Any),
+            name => Ok(GenericFact::Only(datatype_from_str(name)?)),
+        }
+    }
+}
+
+/// Returns the shape of a tensor as a shape fact.
+fn shape_of(tensor: &Tensor) -> ShapeFact {
+    ShapeFact::closed(tensor.shape().iter().map(|&d| DimFact::Only(d)).collect())
+}
+
+/// Writes a fact in the text format. Known values are written along with
+/// their own datatype and shape, which the other fields can't contradict.
+impl fmt::Display for TensorFact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            GenericFact::Any => write!(f, "{}{}", self.datatype, self.shape),
+            GenericFact::Only(tensor) => {
+                write!(f, "{}{}=", datatype_to_str(tensor.datatype()), shape_of(tensor))?;
+
+                match tensor {
+                    Tensor::F32(a) => write_elements(f, a),
+                    Tensor::F64(a) => write_elements(f, a),
+                    Tensor::I32(a) => write_elements(f, a),
+                    Tensor::I8(a) => write_elements(f, a),
+                    Tensor::U8(a) => write_elements(f, a),
+                    Tensor::String(a) => write_elements(f, a),
+                }
+            }
+        }
+    }
+}
+
+/// Parses a fact from its text format.
+///
+/// Facts are written with the same notation as `shapefact!`, prefixed by
+/// their datatype and possibly followed by their value, which is given as
+/// a flat list of elements in row-major order. For instance:
+/// - `_[..]` is the most general fact;
+/// - `f32[S,40,_,..]` is a f32 tensor of rank at least 3, streamed along
+///   its first dimension and whose second dimension is 40;
+/// - `i32[2,2]=1,2,3,4` is a fully known 2x2 matrix;
+/// - `f32[0]=` is an empty vector;
+/// - `string[2]=104,105` is a string tensor, whose elements are bytes;
+/// - `DT_INT64[_]` is a vector of a datatype which has no short name.
+impl FromStr for TensorFact {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TensorFact> {
+        let s = s.trim();
+        let (fact, elements) = match s.find('=') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let (datatype, shape) = match fact.find('[') {
+            Some(i) => (fact[..i].parse()?, fact[i..].parse()?),
+            None => (fact.parse()?, ShapeFact::open(vec![])),
+        };
+
+        let value = match elements {
+            None => GenericFact::Any,
+            Some(elements) => {
+                let shape = match shape.concretize() {
+                    Some(shape) => shape,
+                    None => bail!("The value of {:?} should have a known shape.", s),
+                };
+
+                let tensor = match datatype {
+                    GenericFact::Only(DataType::DT_FLOAT) => Tensor::F32(parse_elements(shape, elements)?),
+                    GenericFact::Only(DataType::DT_DOUBLE) => Tensor::F64(parse_elements(shape, elements)?),
+                    GenericFact::Only(DataType::DT_INT32) => Tensor::I32(parse_elements(shape, elements)?),
+                    GenericFact::Only(DataType::DT_INT8) => Tensor::I8(parse_elements(shape, elements)?),
+                    GenericFact::Only(DataType::DT_UINT8) => Tensor::U8(parse_elements(shape, elements)?),
+                    GenericFact::Only(DataType::DT_STRING) => Tensor::String(parse_elements(shape, elements)?),
+                    _ => bail!("Can't parse the value of {:?}.", s),
+                };
+
+                GenericFact::Only(tensor)
+            }
+        };
+
+        Ok(TensorFact { datatype, shape, value })
+    }
+}
+
+impl TensorFact {
+    /// Returns a description of the fact for display purposes, in which the
+    /// elements of values which have more than `max_elements` of them are
+    /// elided as `=...`.
+    pub fn summary(&self, max_elements: usize) -> String {
+        match &self.value {
+            GenericFact::Only(tensor) if tensor.len() > max_elements => {
+                format!("{}{}=...", datatype_to_str(tensor.datatype()), shape_of(tensor))
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr2;
+
+    /// Checks that a fact is written back exactly as it was parsed.
+    fn round_trip(s: &str) -> TensorFact {
+        let fact: TensorFact = s.parse().unwrap();
+        assert_eq!(fact.to_string(), s);
+        assert_eq!(fact.to_string().parse::<TensorFact>().unwrap(), fact);
+        fact
+    }
+
+    #[test]
+    fn round_trip_any() {
+        let fact = round_trip("_[..]");
+        assert_eq!(fact, TensorFact::new());
+    }
+
+    #[test]
+    fn round_trip_shapes() {
+        round_trip("f32[]");
+        round_trip("i32[2,_,3]");
+        round_trip("u8[_,..]");
+        round_trip("f64[1,2,..]");
+    }
+
+    #[test]
+    fn round_trip_streamed() {
+        let fact = round_trip("f32[S,40,_,..]");
+        assert_eq!(fact.datatype, typefact!(DataType::DT_FLOAT));
+        assert_eq!(fact.shape, shapefact![S, 40, _; ..]);
+    }
+
+    #[test]
+    fn round_trip_values() {
+        let fact = round_trip("i32[2,2]=1,2,3,4");
+        assert_eq!(fact.value.concretize(), Some(Tensor::I32(arr2(&[[1, 2], [3, 4]]).into_dyn())));
+
+        round_trip("f32[3]=0.5,-1.0,2.0");
+        round_trip("i8[]=-3");
+    }
+
+    #[test]
+    fn round_trip_empty_tensors() {
+        let fact = round_trip("f32[0]=");
+        assert_eq!(fact.value.concretize().map(|t| t.shape().to_vec()), Some(vec![0]));
+
+        round_trip("i32[2,0]=");
+    }
+
+    #[test]
+    fn reject_invalid_facts() {
+        assert!("f32[2,2]=1,2,3".parse::<TensorFact>().is_err());
+        assert!("f32[_]=1".parse::<TensorFact>().is_err());
+        assert!("f16[2]".parse::<TensorFact>().is_err());
+        assert!("f32[2".parse::<TensorFact>().is_err());
+    }
+
+    #[test]
+    fn round_trip_unnamed_datatypes() {
+        let fact = round_trip("DT_INT64[2,_]");
+        assert_eq!(fact.datatype, typefact!(DataType::DT_INT64));
+
+        round_trip("DT_BOOL[S,..]");
+        round_trip("DT_BFLOAT16[]");
+        assert!("DT_NOTHING[]".parse::<TensorFact>().is_err());
+    }
+
+    #[test]
+    fn round_trip_strings() {
+        round_trip("string[2]=104,105");
+    }
+
+    #[test]
+    fn write_values_with_their_datatype() {
+        let fact = TensorFact {
+            value: valuefact!(Tensor::I32(arr2(&[[1, 2]]).into_dyn())),
+            ..TensorFact::new()
+        };
+
+        assert_eq!(fact.to_string(), "i32[1,2]=1,2");
+
+        let parsed: TensorFact = fact.to_string().parse().unwrap();
+        assert_eq!(parsed.value, fact.value);
+    }
+
+    #[test]
+    fn summarize_facts() {
+        assert_eq!("i32[2,2]=1,2,3,4".parse::<TensorFact>().unwrap().summary(4), "i32[2,2]=1,2,3,4");
+        assert_eq!("i32[2,2]=1,2,3,4".parse::<TensorFact>().unwrap().summary(3), "i32[2,2]=...");
+
+        let fact = TensorFact {
+            datatype: typefact!(DataType::DT_BFLOAT16),
+            shape: shapefact![S, 2],
+            ..TensorFact::new()
+        };
+
+        assert_eq!(fact.summary(16), "DT_BFLOAT16[S,2]");
+    }
+}
@@ -0,0 +1,503 @@
+use super::prelude::*;
+use super::Analyser;
+use super::Result;
+use ndarray::ArrayD;
+use std::collections::HashMap;
+use tfpb::types::DataType;
+use Tensor;
+
+/// The alignment of the tensors in the arena, in bytes.
+pub const ALIGNMENT: usize = 16;
+
+/// Returns the size of an element of the given datatype, in bytes.
+pub fn datatype_size(datatype: DataType) -> Option<usize> {
+    match datatype {
+        DataType::DT_FLOAT | DataType::DT_INT32 => Some(4),
+        DataType::DT_DOUBLE => Some(8),
+        DataType::DT_INT8 | DataType::DT_UINT8 => Some(1),
+        _ => None,
+    }
+}
+
+/// Returns the size of the tensors described by a fact, in bytes, if it is
+/// known ahead of time.
+pub fn fact_size(fact: &TensorFact) -> Option<usize> {
+    let size = datatype_size(fact.datatype.concretize()?)?;
+    let shape = fact.shape.concretize()?;
+
+    Some(shape.iter().product::<usize>() * size)
+}
+
+/// The policy used to assign offsets to the tensors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    // The largest tensors are placed first, each at the lowest offset where
+    // it doesn't overlap with the tensors already placed.
+    GreedyBySize,
+
+    // The tensors are placed in the order in which they are produced, each
+    // in the smallest gap large enough to hold it.
+    BestFit,
+}
+
+/// The location of the value of an output of a node in the arena.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub node: usize,
+    pub port: usize,
+    pub datatype: DataType,
+    pub shape: Vec<usize>,
+    pub offset: usize,
+    pub size: usize,
+
+    // The positions in the plan of the node which produces the tensor and
+    // of the last node which uses it.
+    pub first: usize,
+    pub last: usize,
+}
+
+impl Allocation {
+    /// Returns whether the lifetimes of two tensors intersect.
+    fn lives_with(&self, other: &Allocation) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+}
+
+/// A static memory plan, i.e. an offset in a preallocated arena for the
+/// value of every output used in the execution plan.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct MemoryPlan {
+    pub allocations: Vec<Allocation>,
+
+    // The size of the arena, in bytes.
+    pub arena_size: usize,
+
+    // The maximum number of bytes used by the tensors alive at the same
+    // time, without padding, which is a lower bound of the size of the
+    // arena.
+    pub peak: usize,
+}
+
+impl MemoryPlan {
+    /// Returns the allocation of an output of a node.
+    pub fn get(&self, node: usize, port: usize) -> Option<&Allocation> {
+        self.allocations.iter().find(|a| a.node == node && a.port == port)
+    }
+}
+
+/// The lifetime of the value of an output of a node in the plan.
+#[derive(Debug, Clone)]
+pub struct Lifetime {
+    pub node: usize,
+    pub port: usize,
+
+    // One of the edges which carry the value.
+    pub edge: usize,
+
+    // The positions in the plan of the node which produces the value and of
+    // the last node which uses it, which is past the end of the plan for
+    // the output of the graph.
+    pub first: usize,
+    pub last: usize,
+}
+
+/// Computes the lifetime of the value of every output used in the plan,
+/// ordered by producer and port.
+pub fn lifetimes(analyser: &Analyser) -> Vec<Lifetime> {
+    let mut position = vec![None; analyser.nodes.len()];
+    for (i, &n) in analyser.plan.iter().enumerate() {
+        position[n] = Some(i);
+    }
+
+    let mut lifetimes: Vec<Lifetime> = vec![];
+
+    for (first, &n) in analyser.plan.iter().enumerate() {
+        let start = lifetimes.len();
+
+        for &e in &analyser.next_edges[n] {
+            let edge = &analyser.edges[e];
+
+            // The value of the output of the graph is needed after the end.
+            let last = match edge.to_node {
+                Some(to) => match position[to] {
+                    Some(last) => last,
+                    None => continue,
+                },
+                None => analyser.plan.len(),
+            };
+
+            match lifetimes[start..].iter_mut().find(|l| l.port == edge.from_out) {
+                Some(lifetime) => lifetime.last = lifetime.last.max(last),
+                None => lifetimes.push(Lifetime { node: n, port: edge.from_out, edge: e, first, last }),
+            }
+        }
+
+        lifetimes[start..].sort_by_key(|l| l.port);
+    }
+
+    lifetimes
+}
+
+/// Returns the maximum of the sum of the sizes of the values which are
+/// alive at the same time.
+///
+/// The values of Const nodes don't count, as they are stored in the graph
+/// itself.
+pub fn peak<F: Fn(&Lifetime) -> usize>(analyser: &Analyser, lifetimes: &[Lifetime], size: F) -> usize {
+    let mut live = vec![0; analyser.plan.len() + 1];
+
+    for lifetime in lifetimes {
+        if analyser.nodes[lifetime.node].op_name == "Const" {
+            continue;
+        }
+
+        let size = size(lifetime);
+        for t in lifetime.first..lifetime.last + 1 {
+            live[t] += size;
+        }
+    }
+
+    live.into_iter().max().unwrap_or(0)
+}
+
+/// Computes the allocation of the value of every output used in the plan.
+///
+/// The values of Const nodes are not part of the arena, as they are stored
+/// in the graph itself.
+fn allocations(analyser: &Analyser, lifetimes: &[Lifetime]) -> Result<Vec<Allocation>> {
+    let mut allocations = vec![];
+
+    for lifetime in lifetimes {
+        let node = &analyser.nodes[lifetime.node];
+        if node.op_name == "Const" {
+            continue;
+        }
+
+        let fact = &analyser.edges[lifetime.edge].fact;
+        let (datatype, shape) = match (fact.datatype.concretize(), fact.shape.concretize()) {
+            (Some(datatype), Some(shape)) => (datatype, shape),
+            _ => bail!(
+                "The size of output #{} of {} isn't known, run the analyser first.",
+                lifetime.port, node.name
+            ),
+        };
+
+        let size = match fact_size(fact) {
+            Some(size) => (size + ALIGNMENT - 1) / ALIGNMENT * ALIGNMENT,
+            None => bail!("Tensors of type {:?} can't be stored in the arena.", datatype),
+        };
+
+        allocations.push(Allocation {
+            node: lifetime.node,
+            port: lifetime.port,
+            datatype,
+            shape,
+            offset: 0,
+            size,
+            first: lifetime.first,
+            last: lifetime.last,
+        });
+    }
+
+    Ok(allocations)
+}
+
+/// Returns the gaps between the tensors which are placed and alive at the
+/// same time as the given one, as (offset, size) pairs. The last gap has no
+/// upper bound.
+fn gaps(placed: &[Allocation], allocation: &Allocation) -> Vec<(usize, Option<usize>)> {
+    let mut used: Vec<_> = placed
+        .iter()
+        .filter(|p| p.lives_with(allocation))
+        .map(|p| (p.offset, p.offset + p.size))
+        .collect();
+
+    used.sort();
+
+    let mut gaps = vec![];
+    let mut offset = 0;
+
+    for (start, end) in used {
+        if start > offset {
+            gaps.push((offset, Some(start - offset)));
+        }
+
+        offset = offset.max(end);
+    }
+
+    gaps.push((offset, None));
+    gaps
+}
+
+/// Copies the elements of a tensor into the slice of the arena given by
+/// its allocation, which must have the same datatype and shape.
+fn store(allocation: &Allocation, tensor: &Tensor, bytes: &mut [u8]) -> Result<()> {
+    if tensor.datatype() != allocation.datatype || tensor.shape() != &allocation.shape[..] {
+        bail!(
+            "Expected a tensor of type {:?} and shape {:?} for output #{} of node {}, found {:?} and {:?}.",
+            allocation.datatype, allocation.shape, allocation.port, allocation.node,
+            tensor.datatype(), tensor.shape()
+        );
+    }
+
+    macro_rules! store {
+        ($array:expr, $size:expr, $bits:expr) => {
+            for (chunk, value) in bytes.chunks_mut($size).zip($array.iter()) {
+                let bits = $bits(value) as u64;
+                for (k, byte) in chunk.iter_mut().enumerate() {
+                    *byte = (bits >> (8 * k)) as u8;
+                }
+            }
+        };
+    }
+
+    match tensor {
+        Tensor::F32(a) => store!(a, 4, |v: &f32| v.to_bits()),
+        Tensor::F64(a) => store!(a, 8, |v: &f64| v.to_bits()),
+        Tensor::I32(a) => store!(a, 4, |v: &i32| *v as u32),
+        Tensor::I8(a) => store!(a, 1, |v: &i8| *v as u8),
+        Tensor::U8(a) => store!(a, 1, |v: &u8| *v),
+        _ => bail!("Tensors of type {:?} can't be stored in the arena.", tensor.datatype()),
+    }
+
+    Ok(())
+}
+
+/// Reads a tensor back from a slice of the arena.
+fn load(allocation: &Allocation, bytes: &[u8]) -> Result<Tensor> {
+    macro_rules! load {
+        ($variant:ident, $size:expr, $from:expr) => {{
+            let len = allocation.shape.iter().product();
+            let values = bytes
+                .chunks($size)
+                .take(len)
+                .map(|c| $from(c.iter().rev().fold(0u64, |acc, &b| acc << 8 | b as u64)))
+                .collect();
+
+            Tensor::$variant(ArrayD::from_shape_vec(allocation.shape.clone(), values)?)
+        }};
+    }
+
+    let tensor = match allocation.datatype {
+        DataType::DT_FLOAT => load!(F32, 4, |b: u64| f32::from_bits(b as u32)),
+        DataType::DT_DOUBLE => load!(F64, 8, |b: u64| f64::from_bits(b)),
+        DataType::DT_INT32 => load!(I32, 4, |b: u64| b as u32 as i32),
+        DataType::DT_INT8 => load!(I8, 1, |b: u64| b as u8 as i8),
+        DataType::DT_UINT8 => load!(U8, 1, |b: u64| b as u8),
+        dt => bail!("Tensors of type {:?} can't be stored in the arena.", dt),
+    };
+
+    Ok(tensor)
+}
+
+impl Analyser {
+    /// Computes a static memory plan for the current execution plan, using
+    /// the facts inferred by the analyser.
+    ///
+    /// All the tensors must have a known datatype and shape.
+    pub fn plan_memory(&self, policy: Policy) -> Result<MemoryPlan> {
+        let lifetimes = lifetimes(self);
+        let mut allocations = allocations(self, &lifetimes)?;
+
+        let mut order: Vec<_> = (0..allocations.len()).collect();
+        if policy == Policy::GreedyBySize {
+            order.sort_by_key(|&i| (::std::cmp::Reverse(allocations[i].size), allocations[i].first));
+        }
+
+        let mut placed: Vec<Allocation> = vec![];
+
+        for i in order {
+            let size = allocations[i].size;
+            let fits = gaps(&placed, &allocations[i])
+                .into_iter()
+                .filter(|&(_, gap)| gap.map(|g| g >= size).unwrap_or(true));
+
+            let offset = match policy {
+                Policy::GreedyBySize => fits.map(|(offset, _)| offset).next(),
+                Policy::BestFit => fits
+                    .min_by_key(|&(offset, gap)| (gap.unwrap_or(usize::max_value()), offset))
+                    .map(|(offset, _)| offset),
+            };
+
+            allocations[i].offset = offset.unwrap_or(0);
+            placed.push(allocations[i].clone());
+        }
+
+        let arena_size = allocations.iter().map(|a| a.offset + a.size).max().unwrap_or(0);
+        let peak = peak(self, &lifetimes, |l| fact_size(&self.edges[l.edge].fact).unwrap_or(0));
+
+        info!("Planned an arena of {} bytes, with a peak usage of {} bytes.", arena_size, peak);
+
+        Ok(MemoryPlan { allocations, arena_size, peak })
+    }
+
+    /// Checks a memory plan by running the graph with a single arena.
+    ///
+    /// The value of every output is stored in the arena at the offset given
+    /// by the plan, and the nodes which use it load it back from the arena.
+    /// Before that, the bytes in the arena are compared with the ones which
+    /// were stored when the value was produced, so that an allocation which
+    /// overlaps with another one while they are both alive is reported as a
+    /// corruption instead of silently producing wrong results.
+    ///
+    /// Returns the values of the outputs of the output node.
+    pub fn validate_plan(&self, memory: &MemoryPlan, inputs: Vec<(usize, Tensor)>) -> Result<Vec<Tensor>> {
+        let mut arena = vec![0u8; memory.arena_size];
+        let inputs: HashMap<_, _> = inputs.into_iter().collect();
+        let mut stored: HashMap<(usize, usize), Vec<u8>> = HashMap::new();
+        let mut result = vec![];
+
+        for &n in &self.plan {
+            let node = &self.nodes[n];
+
+            let outputs = if let Some(tensor) = inputs.get(&n) {
+                vec![tensor.clone()]
+            } else if node.op_name == "Const" {
+                continue;
+            } else {
+                let mut values = vec![];
+                for &(i, port) in &node.inputs {
+                    let port = port.unwrap_or(0);
+                    if self.nodes[i].op_name == "Const" {
+                        values.push(self.nodes[i].op.eval(vec![])?.pop().unwrap());
+                        continue;
+                    }
+
+                    let allocation = memory
+                        .get(i, port)
+                        .ok_or_else(|| format!("Output #{} of node {} isn't in the arena.", port, i))?;
+
+                    let bytes = &arena[allocation.offset..allocation.offset + allocation.size];
+                    if stored.get(&(i, port)).map(|s| &s[..]) != Some(bytes) {
+                        bail!(
+                            "Output #{} of {} was corrupted in the arena before {} used it.",
+                            port, self.nodes[i].name, node.name
+                        );
+                    }
+
+                    values.push(load(allocation, bytes)?.into());
+                }
+
+                node.op
+                    .eval(values)
+                    .map_err(|e| format!("While evaluating {}: {}", node.name, e))?
+                    .into_iter()
+                    .map(|t| t.into_tensor())
+                    .collect()
+            };
+
+            for (port, tensor) in outputs.iter().enumerate() {
+                if let Some(allocation) = memory.get(n, port) {
+                    let bytes = &mut arena[allocation.offset..allocation.offset + allocation.size];
+                    store(allocation, tensor, bytes)?;
+                    stored.insert((n, port), bytes.to_vec());
+                }
+            }
+
+            if n == self.output {
+                result = outputs;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfpb;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::DT_FLOAT;
+    use Model;
+
+    fn f32_node(name: &str, op: &str, inputs: &[&str]) -> NodeDef {
+        inputs
+            .iter()
+            .fold(tfpb::node().name(name.to_string()).op(op).attr("T", DT_FLOAT), |node, i| {
+                node.input(i.to_string())
+            })
+    }
+
+    /// Builds `(Sigmoid(Relu(input)) + Tanh(Relu(input))) * Relu(input)`,
+    /// in which the value of relu lives during the whole plan.
+    fn graph() -> tfpb::graph::GraphDef {
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(f32_node("relu", "Relu", &["input"]))
+            .node(f32_node("sigmoid", "Sigmoid", &["relu"]))
+            .node(f32_node("tanh", "Tanh", &["relu"]))
+            .node(f32_node("add", "Add", &["sigmoid", "tanh"]))
+            .node(f32_node("mul", "Mul", &["add", "relu"]))
+    }
+
+    fn analyser() -> Analyser {
+        let model = Model::new(graph()).unwrap();
+        let input = model.nodes_by_name["input"];
+        let output = model.nodes_by_name["mul"];
+
+        let mut analyser = Analyser::new(model, output).unwrap();
+        let fact = TensorFact {
+            datatype: typefact!(DT_FLOAT),
+            shape: shapefact![2, 3],
+            value: valuefact!(_),
+        };
+
+        analyser.hint(input, &fact).unwrap();
+        analyser.run().unwrap();
+        analyser
+    }
+
+    #[test]
+    fn no_overlap_between_live_tensors() {
+        let analyser = analyser();
+
+        for &policy in &[Policy::GreedyBySize, Policy::BestFit] {
+            let memory = analyser.plan_memory(policy).unwrap();
+            assert_eq!(memory.allocations.len(), 5);
+            assert!(memory.arena_size >= memory.peak);
+
+            for a in &memory.allocations {
+                assert_eq!(a.offset % ALIGNMENT, 0);
+
+                for b in &memory.allocations {
+                    if (a.node, a.port) != (b.node, b.port) && a.lives_with(b) {
+                        assert!(
+                            a.offset + a.size <= b.offset || b.offset + b.size <= a.offset,
+                            "{:?} overlaps with {:?} with policy {:?}.", a, b, policy
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validate_plan_like_evaluation() {
+        let analyser = analyser();
+        let model = Model::new(graph()).unwrap();
+        let input = model.nodes_by_name["input"];
+        let output = model.nodes_by_name["mul"];
+
+        for &policy in &[Policy::GreedyBySize, Policy::BestFit] {
+            let memory = analyser.plan_memory(policy).unwrap();
+            let value = Tensor::F32(ArrayD::from_shape_fn(vec![2, 3], |_| ::rand::random::<f32>() * 2. - 1.));
+
+            let expected = model.run(vec![(input, value.clone())], output).unwrap();
+            let found = analyser.validate_plan(&memory, vec![(input, value)]).unwrap();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn fail_to_store_mismatching_tensor() {
+        let analyser = analyser();
+        let memory = analyser.plan_memory(Policy::BestFit).unwrap();
+        let input = analyser.nodes.iter().find(|n| n.name == "input").unwrap().id;
+        let value = Tensor::F32(ArrayD::from_elem(vec![3, 2], 1.));
+
+        assert!(analyser.validate_plan(&memory, vec![(input, value)]).is_err());
+    }
+}
@@ -0,0 +1,688 @@
+use analyser::interface::expressions::{Expression, IntoExpression, Output, ScaledExpression};
+use analyser::interface::path::Path;
+use analyser::interface::solver::Context;
+use analyser::types::{DimFact, Fact, IntFact, ShapeFact, SpecialKind, TensorFact, TypeFact, ValueFact};
+use Result;
+use Tensor;
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Index;
+use typed_arena::Arena;
+
+/// The proxies built by a parent proxy, e.g. the proxies of the dimensions
+/// built by a ShapeProxy.
+///
+/// The proxies must be returned by reference from `Index::index`, which only
+/// has access to `&self`, so they are allocated in an arena which lives as
+/// long as the parent. A new proxy is allocated on every access, which is
+/// fine since proxies are small and rules only access a handful of them.
+struct Children<V>(Arena<V>);
+
+impl<V> Children<V> {
+    /// Creates an empty set of proxies.
+    fn new() -> Children<V> {
+        Children(Arena::new())
+    }
+
+    /// Stores a proxy, and returns a reference to it.
+    fn add(&self, proxy: V) -> &V {
+        self.0.alloc(proxy)
+    }
+}
+
+/// Cloning a proxy doesn't clone the proxies it has built, which can be
+/// built again when they are needed.
+impl<V> Clone for Children<V> {
+    fn clone(&self) -> Children<V> {
+        Children::new()
+    }
+}
+
+/// The side of the context that a proxy refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Inputs,
+    Outputs,
+}
+
+impl Side {
+    /// Returns the first component of the paths on this side.
+    fn index(self) -> isize {
+        match self {
+            Side::Inputs => 0,
+            Side::Outputs => 1,
+        }
+    }
+
+    /// Returns the facts on this side of the context.
+    fn facts(self, context: &Context) -> &Vec<TensorFact> {
+        match self {
+            Side::Inputs => &context.inputs,
+            Side::Outputs => &context.outputs,
+        }
+    }
+
+    /// Returns a mutable reference to the facts on this side of the context.
+    fn facts_mut(self, context: &mut Context) -> &mut Vec<TensorFact> {
+        match self {
+            Side::Inputs => &mut context.inputs,
+            Side::Outputs => &mut context.outputs,
+        }
+    }
+
+    /// Returns the number of tensors on this side of the context.
+    fn len(self, context: &Context) -> IntFact {
+        match self {
+            Side::Inputs => context.inputs_len,
+            Side::Outputs => context.outputs_len,
+        }
+    }
+
+    /// Checks that there can be a tensor at the given index.
+    fn check(self, context: &Context, index: usize) -> Result<()> {
+        match self.len(context) {
+            IntFact::Only(len) if index as isize >= len => {
+                bail!("There is no tensor #{} in the {:?} of the context.", index, self)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the fact about the tensor at the given index.
+    ///
+    /// If the number of tensors isn't known yet, the tensors after the ones
+    /// in the context are unknown.
+    fn tensor(self, context: &Context, index: usize) -> Result<Cow<TensorFact>> {
+        self.check(context, index)?;
+
+        match self.facts(context).get(index) {
+            Some(fact) => Ok(Cow::Borrowed(fact)),
+            None => Ok(Cow::Owned(TensorFact::new())),
+        }
+    }
+
+    /// Returns a mutable reference to the fact about the tensor at the index,
+    /// adding unknown tensors to the context if needed.
+    fn tensor_mut(self, context: &mut Context, index: usize) -> Result<&mut TensorFact> {
+        self.check(context, index)?;
+
+        let facts = self.facts_mut(context);
+        if facts.len() <= index {
+            facts.resize(index + 1, TensorFact::new());
+        }
+
+        Ok(&mut facts[index])
+    }
+}
+
+/// Unifies a field of the context with a value, and records the change.
+fn update<T: Output + Fact + Clone + PartialEq>(
+    context: &mut Context,
+    path: &Path,
+    field: &mut T,
+    value: &T,
+) -> Result<Option<(T, T)>> {
+    let unified = field.unify(value)?;
+
+    if unified == *field {
+        return Ok(None);
+    }
+
+    let before = ::std::mem::replace(field, unified.clone());
+    context.record(path.clone(), before.clone().wrap(), unified.clone().wrap());
+    Ok(Some((before, unified)))
+}
+
+/// Converts a dimension fact into an integer fact.
+fn dim_to_int(dim: &DimFact) -> IntFact {
+    match dim {
+        DimFact::Any => IntFact::Any,
+        DimFact::Streamed => IntFact::Special(SpecialKind::Streamed),
+        DimFact::Only(d) => IntFact::Only(*d as isize),
+    }
+}
+
+/// Converts an integer fact into a dimension fact.
+fn int_to_dim(value: IntFact) -> Result<DimFact> {
+    match value {
+        IntFact::Any => Ok(DimFact::Any),
+        IntFact::Special(SpecialKind::Streamed) => Ok(DimFact::Streamed),
+        IntFact::Only(d) if d >= 0 => Ok(DimFact::Only(d as usize)),
+        _ => bail!("{:?} is not a valid dimension.", value),
+    }
+}
+
+/// Returns the rank of a shape fact.
+fn rank_of(shape: &ShapeFact) -> IntFact {
+    if shape.open {
+        IntFact::Any
+    } else {
+        IntFact::Only(shape.dims.len() as isize)
+    }
+}
+
+/// Unifies the shape of a tensor with a value, and records the change on
+/// the paths of both the shape and the rank of the tensor.
+fn set_shape(context: &mut Context, side: Side, index: usize, value: &ShapeFact) -> Result<()> {
+    let mut shape = side.tensor(context, index)?.shape.clone();
+    let path = vec![side.index(), index as isize, 2].into();
+
+    if let Some((before, after)) = update(context, &path, &mut shape, value)? {
+        if rank_of(&before) != rank_of(&after) {
+            let rank = vec![side.index(), index as isize, 1].into();
+            context.record(rank, rank_of(&before).wrap(), rank_of(&after).wrap());
+        }
+
+        side.tensor_mut(context, index)?.shape = shape;
+    }
+
+    Ok(())
+}
+
+/// A proxy for the number of tensors on one side of the context.
+#[derive(Clone)]
+pub struct LenProxy {
+    side: Side,
+    path: Path,
+}
+
+impl Expression for LenProxy {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        Ok(self.side.len(context))
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    ///
+    /// Once the number of tensors is known, the facts about the tensors
+    /// which weren't known so far are added to the context.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        let mut len = self.side.len(context);
+
+        if update(context, &self.path, &mut len, &value)?.is_none() {
+            return Ok(());
+        }
+
+        match self.side {
+            Side::Inputs => context.inputs_len = len,
+            Side::Outputs => context.outputs_len = len,
+        }
+
+        if let IntFact::Only(len) = len {
+            let facts = self.side.facts_mut(context);
+
+            if len < 0 || (len as usize) < facts.len() {
+                bail!("Expected {} {:?}, but {} were given.", len, self.side, facts.len());
+            }
+
+            facts.resize(len as usize, TensorFact::new());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for the datatype of a tensor.
+#[derive(Clone)]
+pub struct TypeProxy {
+    side: Side,
+    index: usize,
+    path: Path,
+}
+
+impl Expression for TypeProxy {
+    type Output = TypeFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<TypeFact> {
+        Ok(self.side.tensor(context, self.index)?.datatype.clone())
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: TypeFact) -> Result<()> {
+        let mut datatype = self.get(context)?;
+
+        if update(context, &self.path, &mut datatype, &value)?.is_some() {
+            self.side.tensor_mut(context, self.index)?.datatype = datatype;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for the rank of a tensor.
+///
+/// The rank isn't stored separately from the shape: setting it closes the
+/// shape of the tensor with the right number of dimensions.
+#[derive(Clone)]
+pub struct RankProxy {
+    side: Side,
+    index: usize,
+    path: Path,
+}
+
+impl Expression for RankProxy {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        Ok(rank_of(&self.side.tensor(context, self.index)?.shape))
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        match value {
+            IntFact::Only(rank) if rank >= 0 => {
+                let shape = ShapeFact::closed(vec![DimFact::Any; rank as usize]);
+                set_shape(context, self.side, self.index, &shape)
+            }
+
+            IntFact::Any => Ok(()),
+            _ => bail!("{:?} is not a valid rank.", value),
+        }
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for the shape of a tensor.
+///
+/// The dimensions of the shape can be accessed with `shape[i]`.
+#[derive(Clone)]
+pub struct ShapeProxy {
+    side: Side,
+    index: usize,
+    path: Path,
+    dims: Children<DimProxy>,
+}
+
+impl ShapeProxy {
+    /// Creates a new ShapeProxy instance.
+    fn new(side: Side, index: usize) -> ShapeProxy {
+        let path = vec![side.index(), index as isize, 2].into();
+        ShapeProxy { side, index, path, dims: Children::new() }
+    }
+}
+
+impl Index<usize> for ShapeProxy {
+    type Output = DimProxy;
+
+    /// Returns the proxy for the i-th dimension of the shape.
+    fn index(&self, i: usize) -> &DimProxy {
+        self.dims.add(DimProxy {
+            side: self.side,
+            index: self.index,
+            dim: i,
+            path: vec![self.side.index(), self.index as isize, 2, i as isize].into(),
+        })
+    }
+}
+
+impl Expression for ShapeProxy {
+    type Output = ShapeFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<ShapeFact> {
+        Ok(self.side.tensor(context, self.index)?.shape.clone())
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: ShapeFact) -> Result<()> {
+        set_shape(context, self.side, self.index, &value)
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for a single dimension of the shape of a tensor.
+#[derive(Clone)]
+pub struct DimProxy {
+    side: Side,
+    index: usize,
+    dim: usize,
+    path: Path,
+}
+
+impl Expression for DimProxy {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    ///
+    /// If the shape is closed and doesn't have this dimension, this fails.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        let tensor = self.side.tensor(context, self.index)?;
+        let shape = &tensor.shape;
+
+        match shape.dims.get(self.dim) {
+            Some(dim) => Ok(dim_to_int(dim)),
+            None if shape.open => Ok(IntFact::Any),
+            None => bail!("The shape {:?} doesn't have a dimension #{}.", shape, self.dim),
+        }
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        let mut shape = self.side.tensor(context, self.index)?.shape.clone();
+
+        if shape.open && shape.dims.len() <= self.dim {
+            shape.dims.resize(self.dim + 1, DimFact::Any);
+        }
+
+        match shape.dims.get_mut(self.dim) {
+            Some(dim) => *dim = dim.unify(&int_to_dim(value)?)?,
+            None => bail!("The shape {:?} doesn't have a dimension #{}.", shape, self.dim),
+        }
+
+        set_shape(context, self.side, self.index, &shape)
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for the value of a tensor.
+///
+/// The elements of the value can be accessed with `value[i][j]`.
+#[derive(Clone)]
+pub struct ValueProxy {
+    side: Side,
+    index: usize,
+    path: Path,
+    elements: Children<ElementProxy>,
+}
+
+impl ValueProxy {
+    /// Creates a new ValueProxy instance.
+    fn new(side: Side, index: usize) -> ValueProxy {
+        let path = vec![side.index(), index as isize, 3].into();
+        ValueProxy { side, index, path, elements: Children::new() }
+    }
+}
+
+impl Index<usize> for ValueProxy {
+    type Output = ElementProxy;
+
+    /// Returns the proxy for the i-th element of the value.
+    fn index(&self, i: usize) -> &ElementProxy {
+        self.elements.add(ElementProxy::new(self.side, self.index, vec![i]))
+    }
+}
+
+impl Expression for ValueProxy {
+    type Output = ValueFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<ValueFact> {
+        Ok(self.side.tensor(context, self.index)?.value.clone())
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    fn set(&self, context: &mut Context, value: ValueFact) -> Result<()> {
+        let mut current = self.get(context)?;
+
+        if update(context, &self.path, &mut current, &value)?.is_some() {
+            self.side.tensor_mut(context, self.index)?.value = current;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for a single element of the value of a tensor.
+///
+/// The element is only known once the whole value is. To build a value
+/// from its elements, use `tensor_of` instead of setting them one by one.
+#[derive(Clone)]
+pub struct ElementProxy {
+    side: Side,
+    index: usize,
+    indices: Vec<usize>,
+    path: Path,
+    children: Children<ElementProxy>,
+}
+
+impl ElementProxy {
+    /// Creates a new ElementProxy instance.
+    fn new(side: Side, index: usize, indices: Vec<usize>) -> ElementProxy {
+        let mut path = vec![side.index(), index as isize, 3];
+        path.extend(indices.iter().map(|&i| i as isize));
+
+        ElementProxy { side, index, indices, path: path.into(), children: Children::new() }
+    }
+
+    /// Returns the element of a tensor at the indices of the proxy.
+    fn element(&self, tensor: &Tensor) -> Result<isize> {
+        let value = match tensor {
+            Tensor::I32(array) => array.get(&self.indices[..]).map(|&v| v as isize),
+            Tensor::I8(array) => array.get(&self.indices[..]).map(|&v| v as isize),
+            Tensor::U8(array) => array.get(&self.indices[..]).map(|&v| v as isize),
+            _ => bail!("Only the elements of integer tensors can be accessed."),
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => bail!("There is no element at {:?} in {:?}.", self.indices, tensor),
+        }
+    }
+}
+
+impl Index<usize> for ElementProxy {
+    type Output = ElementProxy;
+
+    /// Returns the proxy for the i-th element along the next axis.
+    fn index(&self, i: usize) -> &ElementProxy {
+        let mut indices = self.indices.clone();
+        indices.push(i);
+        self.children.add(ElementProxy::new(self.side, self.index, indices))
+    }
+}
+
+impl Expression for ElementProxy {
+    type Output = IntFact;
+
+    /// Returns the current value of the expression in the given context.
+    fn get(&self, context: &Context) -> Result<IntFact> {
+        match self.side.tensor(context, self.index)?.value.concretize() {
+            Some(tensor) => Ok(IntFact::Only(self.element(&tensor)?)),
+            None => Ok(IntFact::Any),
+        }
+    }
+
+    /// Tries to set the value of the expression in the given context.
+    ///
+    /// This only checks that the value is compatible with the element.
+    fn set(&self, context: &mut Context, value: IntFact) -> Result<()> {
+        self.get(context)?.unify(&value)?;
+        Ok(())
+    }
+
+    /// Returns the paths that the expression depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![&self.path]
+    }
+}
+
+/// A proxy for a tensor, which gives access to its properties.
+#[derive(Clone)]
+pub struct TensorProxy {
+    pub datatype: TypeProxy,
+    pub rank: RankProxy,
+    pub shape: ShapeProxy,
+    pub value: ValueProxy,
+}
+
+impl TensorProxy {
+    /// Creates a new TensorProxy instance.
+    fn new(side: Side, index: usize) -> TensorProxy {
+        let path = |field: isize| -> Path { vec![side.index(), index as isize, field].into() };
+
+        TensorProxy {
+            datatype: TypeProxy { side, index, path: path(0) },
+            rank: RankProxy { side, index, path: path(1) },
+            shape: ShapeProxy::new(side, index),
+            value: ValueProxy::new(side, index),
+        }
+    }
+}
+
+/// A proxy for the tensors on one side of the context.
+///
+/// This is what rules receive as `inputs` and `outputs`. All the fields are
+/// checked at compile time, and each proxy has the type of the facts it
+/// refers to, so `solver.equals(&inputs.len, DataType::DT_INT32)`
+/// doesn't compile. The proxies read and write the context directly, so no
+/// conversion from wrapped values is needed.
+pub struct TensorsProxy {
+    pub len: LenProxy,
+    side: Side,
+    tensors: Children<TensorProxy>,
+}
+
+impl TensorsProxy {
+    /// Creates a proxy for the tensors on the given side of the context.
+    pub fn new(side: Side) -> TensorsProxy {
+        TensorsProxy {
+            len: LenProxy { side, path: vec![side.index(), -1].into() },
+            side,
+            tensors: Children::new(),
+        }
+    }
+}
+
+impl Index<usize> for TensorsProxy {
+    type Output = TensorProxy;
+
+    /// Returns the proxy for the i-th tensor.
+    ///
+    /// Any index can be used, even if the number of tensors isn't known yet,
+    /// but rules which use a tensor that doesn't exist fail once applied.
+    fn index(&self, i: usize) -> &TensorProxy {
+        self.tensors.add(TensorProxy::new(self.side, i))
+    }
+}
+
+macro_rules! impl_proxy {
+    ($proxy:ident) => {
+        impl<'a> IntoExpression<$proxy> for &'a $proxy {
+            /// Converts the proxy to an Expression.
+            fn into_expr(self) -> $proxy {
+                self.clone()
+            }
+        }
+
+        impl fmt::Debug for $proxy {
+            fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "{:?}", self.path)
+            }
+        }
+    };
+}
+
+impl_proxy!(LenProxy);
+impl_proxy!(TypeProxy);
+impl_proxy!(RankProxy);
+impl_proxy!(ShapeProxy);
+impl_proxy!(DimProxy);
+impl_proxy!(ValueProxy);
+impl_proxy!(ElementProxy);
+
+macro_rules! impl_scaled_proxy {
+    ($proxy:ident) => {
+        impl<'a> IntoExpression<ScaledExpression> for (isize, &'a $proxy) {
+            /// Converts the scaled proxy to an Expression.
+            fn into_expr(self) -> ScaledExpression {
+                ScaledExpression::new(self.0, Box::new(self.1.clone()))
+            }
+        }
+    };
+}
+
+impl_scaled_proxy!(LenProxy);
+impl_scaled_proxy!(RankProxy);
+impl_scaled_proxy!(DimProxy);
+impl_scaled_proxy!(ElementProxy);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::solver::Solver;
+    use analyser::prelude::*;
+    use ndarray::arr1;
+
+    fn i32s(values: &[i32]) -> TensorFact {
+        tensor_to_fact(Tensor::I32(arr1(values).into_dyn()))
+    }
+
+    #[test]
+    fn access_dimensions_of_any_rank() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.equals(&inputs[0].rank, 12).equals(&inputs[0].shape[11], 3);
+
+        let (inputs, _) = solver.infer((vec![TensorFact::new()], vec![])).unwrap();
+        assert_eq!(inputs[0].shape.dims[11], DimFact::Only(3));
+    }
+
+    #[test]
+    fn fail_on_missing_dimension() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.equals(&inputs[0].shape[2], 3);
+
+        assert!(solver.infer((vec![i32s(&[1, 2])], vec![])).is_err());
+    }
+
+    #[test]
+    fn fail_on_missing_element() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.equals(&inputs[0].value[5], 0);
+
+        assert!(solver.infer((vec![i32s(&[1, 2])], vec![])).is_err());
+    }
+
+    #[test]
+    fn fail_on_missing_tensor() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.equals(&inputs[3].rank, 1);
+
+        assert!(solver.infer((vec![i32s(&[1, 2])], vec![])).is_err());
+    }
+
+    #[test]
+    fn clone_without_built_proxies() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let value = &inputs[0].value;
+        let _ = &value[1][2];
+
+        let path: Path = vec![0, 0, 3, 1, 2].into();
+        assert_eq!(value.clone()[1][2].get_paths(), vec![&path]);
+    }
+}
@@ -0,0 +1,414 @@
+use super::prelude::*;
+use super::Analyser;
+use super::Result;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// A command of the interactive session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Runs the given number of steps, or stops at a breakpoint.
+    Step(usize),
+
+    // Runs steps until reaching a fixed point, or stops at a breakpoint.
+    Run,
+
+    // Prints the fact on an edge.
+    Fact(usize),
+
+    // Adds a hint about the outputs of a node.
+    Hint(String, TensorFact),
+
+    // Adds, removes or lists the breakpoints on edges.
+    Break(usize),
+    Delete(usize),
+    Breakpoints,
+
+    // Prints the current state of the analysis, or the whole graph.
+    Status,
+    Graph,
+
+    Help,
+    Quit,
+}
+
+/// The maximum number of elements of the values which are printed.
+const MAX_ELEMENTS: usize = 64;
+
+const HELP: &str = "\
+step [n]            run n steps of the analysis (1 by default)
+run                 run the analysis until reaching a fixed point
+fact <edge>         print the fact on an edge
+hint <node> <fact>  add a hint about the outputs of a node, e.g. f32[1,S,40]
+break <edge>        stop stepping when the fact on an edge changes
+delete <edge>       remove the breakpoint on an edge
+breakpoints         list the breakpoints
+status              print the current pass, step and direction
+graph               print the nodes and the facts on their edges
+help                print this message
+quit                leave the session";
+
+impl Command {
+    /// Parses a command from a line of input.
+    pub fn parse(line: &str) -> Result<Command> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<_> = words.collect();
+
+        let edge = |args: &[&str]| -> Result<usize> {
+            match args.get(0).map(|a| a.parse()) {
+                Some(Ok(edge)) => Ok(edge),
+                _ => bail!("Expected an edge index."),
+            }
+        };
+
+        let command = match name {
+            "step" | "s" => match args.get(0) {
+                Some(n) => Command::Step(n.parse().map_err(|_| format!("Invalid number of steps {}.", n))?),
+                None => Command::Step(1),
+            },
+            "run" | "r" => Command::Run,
+            "fact" | "f" => Command::Fact(edge(&args)?),
+            "hint" => {
+                if args.len() != 2 {
+                    bail!("Usage: hint <node> <fact>.");
+                }
+
+                Command::Hint(args[0].to_string(), args[1].parse()?)
+            }
+            "break" | "b" => Command::Break(edge(&args)?),
+            "delete" | "d" => Command::Delete(edge(&args)?),
+            "breakpoints" => Command::Breakpoints,
+            "status" => Command::Status,
+            "graph" | "g" => Command::Graph,
+            "help" | "h" | "?" => Command::Help,
+            "quit" | "q" | "exit" => Command::Quit,
+            _ => bail!("Unknown command {:?}, type help for a list of commands.", name),
+        };
+
+        Ok(command)
+    }
+}
+
+/// An interactive session which steps through the analysis of a graph.
+pub struct Session {
+    pub analyser: Analyser,
+
+    // The edges with a breakpoint, along with their fact when it was last
+    // checked.
+    breakpoints: HashMap<usize, TensorFact>,
+
+    // The number of consecutive steps which didn't change any fact.
+    unchanged: usize,
+}
+
+impl Session {
+    /// Creates a new Session instance.
+    pub fn new(analyser: Analyser) -> Session {
+        Session {
+            analyser,
+            breakpoints: HashMap::new(),
+            unchanged: 0,
+        }
+    }
+
+    /// Returns the index of the node which the next step will analyse, if
+    /// the execution plan isn't empty.
+    fn current_node(&self) -> Option<usize> {
+        let analyser = &self.analyser;
+
+        let position = if analyser.current_direction {
+            analyser.current_step
+        } else {
+            analyser.plan.len().checked_sub(1 + analyser.current_step)?
+        };
+
+        analyser.plan.get(position).cloned()
+    }
+
+    /// Returns the index of a node given its name or its index.
+    fn find_node(&self, node: &str) -> Result<usize> {
+        if let Ok(id) = node.parse::<usize>() {
+            if id < self.analyser.nodes.len() {
+                return Ok(id);
+            }
+        }
+
+        match self.analyser.nodes.iter().find(|n| n.name == node) {
+            Some(n) => Ok(n.id),
+            None => bail!("There is no node named {}.", node),
+        }
+    }
+
+    /// Returns whether the analysis reached a fixed point, i.e. whether two
+    /// whole passes didn't change any fact.
+    pub fn is_done(&self) -> bool {
+        self.unchanged >= 2 * self.analyser.plan.len()
+    }
+
+    /// Runs a single step, and returns the edges with a breakpoint which
+    /// changed during the step.
+    fn step(&mut self) -> Result<Vec<usize>> {
+        if self.analyser.run_step()? {
+            self.unchanged = 0;
+        } else {
+            self.unchanged += 1;
+        }
+
+        let mut hits = vec![];
+        for (&edge, fact) in self.breakpoints.iter_mut() {
+            if self.analyser.edges[edge].fact != *fact {
+                *fact = self.analyser.edges[edge].fact.clone();
+                hits.push(edge);
+            }
+        }
+
+        hits.sort();
+        Ok(hits)
+    }
+
+    /// Runs steps until the condition holds or a breakpoint is hit.
+    fn step_until<F: Fn(&Session, usize) -> bool>(&mut self, done: F) -> Result<String> {
+        let mut steps = 0;
+
+        while !done(self, steps) {
+            let node = match self.current_node() {
+                Some(node) => node,
+                None => bail!("There is no node to analyse, the execution plan is empty."),
+            };
+
+            let hits = self.step()?;
+            steps += 1;
+
+            if !hits.is_empty() {
+                let facts: Vec<_> = hits
+                    .iter()
+                    .map(|&e| format!("edge {}: {}", e, self.analyser.edges[e].fact.summary(MAX_ELEMENTS)))
+                    .collect();
+
+                return Ok(format!(
+                    "Breakpoint hit after {} steps on {}:\n{}",
+                    steps, self.analyser.nodes[node].name, facts.join("\n")
+                ));
+            }
+        }
+
+        Ok(format!("Ran {} steps. {}", steps, self.status()))
+    }
+
+    /// Returns a description of the current state of the analysis.
+    fn status(&self) -> String {
+        let analyser = &self.analyser;
+        let direction = if analyser.current_direction { "forward" } else { "backward" };
+        let next = match self.current_node() {
+            Some(node) => analyser.nodes[node].name.as_str(),
+            None => "none",
+        };
+
+        format!(
+            "Pass {}, step {}/{} ({}), next node: {}{}",
+            analyser.current_pass,
+            analyser.current_step,
+            analyser.plan.len(),
+            direction,
+            next,
+            if self.is_done() { ", fixed point reached." } else { "." },
+        )
+    }
+
+    /// Returns a description of the graph and of the facts on its edges.
+    fn graph(&self) -> String {
+        let analyser = &self.analyser;
+        let mut lines = vec![];
+
+        for &n in &analyser.plan {
+            let node = &analyser.nodes[n];
+            lines.push(format!("#{} {} ({})", node.id, node.name, node.op_name));
+
+            for &e in &analyser.prev_edges[n] {
+                lines.push(format!("    <- edge {}: {}", e, analyser.edges[e].fact.summary(MAX_ELEMENTS)));
+            }
+
+            for &e in &analyser.next_edges[n] {
+                lines.push(format!("    -> edge {}: {}", e, analyser.edges[e].fact.summary(MAX_ELEMENTS)));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// Executes a command, and returns the text to display.
+    pub fn execute(&mut self, command: Command) -> Result<String> {
+        let check_edge = |session: &Session, edge: usize| -> Result<()> {
+            if edge >= session.analyser.edges.len() {
+                bail!("There is no edge with index {}.", edge);
+            }
+
+            Ok(())
+        };
+
+        let output = match command {
+            Command::Step(n) => self.step_until(|_, steps| steps >= n)?,
+            Command::Run => self.step_until(|session, _| session.is_done())?,
+
+            Command::Fact(edge) => {
+                check_edge(self, edge)?;
+                let edge = &self.analyser.edges[edge];
+                let name = |n: Option<usize>, missing: &'static str| match n {
+                    Some(n) => self.analyser.nodes[n].name.as_str(),
+                    None => missing,
+                };
+
+                let fact = edge.fact.summary(MAX_ELEMENTS);
+                format!("{} -> {}: {}", name(edge.from_node, "input"), name(edge.to_node, "output"), fact)
+            }
+
+            Command::Hint(node, fact) => {
+                let node = self.find_node(&node)?;
+                self.analyser.hint(node, &fact)?;
+                self.unchanged = 0;
+
+                format!("Added hint {} to {}.", fact.summary(MAX_ELEMENTS), self.analyser.nodes[node].name)
+            }
+
+            Command::Break(edge) => {
+                check_edge(self, edge)?;
+                self.breakpoints.insert(edge, self.analyser.edges[edge].fact.clone());
+                format!("Added a breakpoint on edge {}.", edge)
+            }
+
+            Command::Delete(edge) => match self.breakpoints.remove(&edge) {
+                Some(_) => format!("Removed the breakpoint on edge {}.", edge),
+                None => format!("There is no breakpoint on edge {}.", edge),
+            },
+
+            Command::Breakpoints => {
+                let mut edges: Vec<_> = self.breakpoints.keys().cloned().collect();
+                edges.sort();
+
+                let edges: Vec<_> = edges.iter().map(|e| e.to_string()).collect();
+                format!("Breakpoints on edges: {}", edges.join(", "))
+            }
+
+            Command::Status => self.status(),
+            Command::Graph => self.graph(),
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        };
+
+        Ok(output)
+    }
+}
+
+/// Runs an interactive session on the given input and output, e.g. on the
+/// standard input and output of a terminal.
+///
+/// Errors in the commands are displayed without ending the session, so
+/// that the analysis can be inspected after it failed.
+pub fn repl<R: BufRead, W: Write>(analyser: Analyser, input: R, mut output: W) -> Result<Analyser> {
+    let mut session = Session::new(analyser);
+    writeln!(output, "{}", session.status())?;
+
+    write!(output, "> ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+
+        if !line.trim().is_empty() {
+            match Command::parse(&line).and_then(|c| {
+                if c == Command::Quit {
+                    return Ok(None);
+                }
+
+                session.execute(c).map(Some)
+            }) {
+                Ok(Some(text)) => writeln!(output, "{}", text)?,
+                Ok(None) => break,
+                Err(e) => writeln!(output, "Error: {}", e)?,
+            }
+        }
+
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+
+    Ok(session.analyser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfpb;
+    use tfpb::types::DataType::DT_FLOAT;
+    use Model;
+
+    /// Builds `Tanh(Relu(input))`.
+    fn analyser() -> Analyser {
+        let graph = tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(tfpb::node().name("relu".to_string()).op("Relu").attr("T", DT_FLOAT).input("input".to_string()))
+            .node(tfpb::node().name("tanh".to_string()).op("Tanh").attr("T", DT_FLOAT).input("relu".to_string()));
+
+        let model = Model::new(graph).unwrap();
+        let output = model.nodes_by_name["tanh"];
+        Analyser::new(model, output).unwrap()
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("step").unwrap(), Command::Step(1));
+        assert_eq!(Command::parse("  s 3 ").unwrap(), Command::Step(3));
+        assert_eq!(Command::parse("run").unwrap(), Command::Run);
+        assert_eq!(Command::parse("f 2").unwrap(), Command::Fact(2));
+        assert_eq!(Command::parse("break 1").unwrap(), Command::Break(1));
+        assert_eq!(Command::parse("d 1").unwrap(), Command::Delete(1));
+        assert_eq!(Command::parse("breakpoints").unwrap(), Command::Breakpoints);
+        assert_eq!(Command::parse("exit").unwrap(), Command::Quit);
+        assert_eq!(
+            Command::parse("hint input f32[2,3]").unwrap(),
+            Command::Hint("input".to_string(), "f32[2,3]".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn fail_on_invalid_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("jump").is_err());
+        assert!(Command::parse("step many").is_err());
+        assert!(Command::parse("fact").is_err());
+        assert!(Command::parse("break -1").is_err());
+        assert!(Command::parse("hint input").is_err());
+    }
+
+    #[test]
+    fn scripted_session() {
+        let analyser = analyser();
+        let tanh = analyser.output;
+        let edge = analyser.prev_edges[tanh][0];
+        let last = analyser.next_edges[tanh][0];
+
+        let script = format!(
+            "hint input f32[2,3]\nbreak {}\nstep\nrun\nrun\nfact {}\nfact {}\njump\nquit\nstep\n",
+            edge, edge, last
+        );
+
+        let mut output = vec![];
+        let analyser = repl(analyser, script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("Added hint f32[2,3] to input."));
+        assert!(output.contains(&format!("Added a breakpoint on edge {}.", edge)));
+        assert!(output.contains("Ran 1 steps."));
+        assert!(output.contains("Breakpoint hit after"));
+        assert!(output.contains(&format!("on relu:\nedge {}: f32[2,3]", edge)));
+        assert!(output.contains("fixed point reached."));
+        assert!(output.contains("relu -> tanh: f32[2,3]"));
+        assert!(output.contains("tanh -> output: f32[2,3]"));
+        assert!(output.contains("Error: Unknown command \"jump\""));
+
+        // The session ends at quit, so the last step isn't run.
+        assert_eq!(output.matches("Ran ").count(), 2);
+        assert_eq!(analyser.edges[last].fact, "f32[2,3]".parse().unwrap());
+    }
+}
@@ -0,0 +1,394 @@
+use analyser::interface::expressions::{Expression, IntoExpression};
+use analyser::interface::path::Path;
+use analyser::interface::proxies::{TensorProxy, TensorsProxy};
+use analyser::interface::solver::{Context, Rule, Solver};
+use analyser::types::{IntFact, SpecialKind};
+use Result;
+
+use std::fmt;
+
+/// The `less_or_equal` rule.
+/// It states that an integer expression must be lower than or equal to
+/// another one, and fails with the given message otherwise.
+///
+/// It can be added to the solver via the following method:
+/// ```text
+/// solver.less_or_equal(&kernel.shape[0], &input.shape[1], "input shorter than kernel");
+/// ```
+struct LessOrEqualRule {
+    left: Box<Expression<Output = IntFact>>,
+    right: Box<Expression<Output = IntFact>>,
+    message: &'static str,
+}
+
+impl LessOrEqualRule {
+    /// Creates a new LessOrEqualRule instance.
+    pub fn new(
+        left: Box<Expression<Output = IntFact>>,
+        right: Box<Expression<Output = IntFact>>,
+        message: &'static str,
+    ) -> LessOrEqualRule {
+        LessOrEqualRule { left, right, message }
+    }
+}
+
+impl<'rules> Rule<'rules> for LessOrEqualRule {
+    /// Tries to apply the rule to a given context.
+    fn apply(&self, context: &mut Context) -> Result<(bool, Vec<Box<Rule<'rules> + 'rules>>)> {
+        use analyser::types::IntFact::*;
+
+        match (self.left.get(context)?, self.right.get(context)?) {
+            (Only(a), Only(b)) if a > b => bail!(
+                "{} ({:?} = {:?} is greater than {:?} = {:?}).",
+                self.message, self.left, a, self.right, b
+            ),
+            (Only(_), Only(_)) => Ok((true, vec![])),
+
+            // Streamed dimensions are unbounded, so they are greater than
+            // any known value, and we can't compare two of them.
+            (Only(_), Special(SpecialKind::Streamed)) => Ok((true, vec![])),
+            (Special(SpecialKind::Streamed), Only(b)) => bail!(
+                "{} ({:?} is streamed, so it is greater than {:?} = {:?}).",
+                self.message, self.left, self.right, b
+            ),
+            (Special(SpecialKind::Streamed), Special(SpecialKind::Streamed)) => Ok((false, vec![])),
+
+            (Any, _) | (_, Any) => Ok((false, vec![])),
+        }
+    }
+
+    /// Returns the paths that the rule depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        let mut paths = self.left.get_paths();
+        paths.extend(self.right.get_paths());
+        paths
+    }
+}
+
+impl fmt::Debug for LessOrEqualRule {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:?} <= {:?}", self.left, self.right)
+    }
+}
+
+/// The `divides` rule.
+/// It states that an integer expression must divide another one, and fails
+/// with the given message otherwise.
+///
+/// It can be added to the solver via the following method:
+/// ```text
+/// solver.divides(&input.shape[3], &kernel.shape[2], "channels don't match groups");
+/// ```
+struct DividesRule {
+    divisor: Box<Expression<Output = IntFact>>,
+    dividend: Box<Expression<Output = IntFact>>,
+    message: &'static str,
+}
+
+impl DividesRule {
+    /// Creates a new DividesRule instance.
+    pub fn new(
+        divisor: Box<Expression<Output = IntFact>>,
+        dividend: Box<Expression<Output = IntFact>>,
+        message: &'static str,
+    ) -> DividesRule {
+        DividesRule { divisor, dividend, message }
+    }
+}
+
+impl<'rules> Rule<'rules> for DividesRule {
+    /// Tries to apply the rule to a given context.
+    fn apply(&self, context: &mut Context) -> Result<(bool, Vec<Box<Rule<'rules> + 'rules>>)> {
+        match (self.divisor.get(context)?, self.dividend.get(context)?) {
+            (IntFact::Only(0), _) => bail!("{} ({:?} is zero).", self.message, self.divisor),
+            (IntFact::Only(a), IntFact::Only(b)) if b % a != 0 => bail!(
+                "{} ({:?} = {:?} doesn't divide {:?} = {:?}).",
+                self.message, self.divisor, a, self.dividend, b
+            ),
+            (IntFact::Only(_), IntFact::Only(_)) => Ok((true, vec![])),
+            _ => Ok((false, vec![])),
+        }
+    }
+
+    /// Returns the paths that the rule depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        let mut paths = self.divisor.get_paths();
+        paths.extend(self.dividend.get_paths());
+        paths
+    }
+}
+
+impl fmt::Debug for DividesRule {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:?} | {:?}", self.divisor, self.dividend)
+    }
+}
+
+/// The `unsupported` rule.
+/// It fails as soon as it is applied, for operations whose attributes aren't
+/// supported by their other rules, so that they don't infer wrong facts.
+///
+/// It can be added to the solver via the following method:
+/// ```text
+/// solver.unsupported("Conv2D only supports the NHWC data format.");
+/// ```
+struct UnsupportedRule {
+    message: &'static str,
+}
+
+impl<'rules> Rule<'rules> for UnsupportedRule {
+    /// Tries to apply the rule to a given context.
+    fn apply(&self, _: &mut Context) -> Result<(bool, Vec<Box<Rule<'rules> + 'rules>>)> {
+        bail!("{}", self.message)
+    }
+
+    /// Returns the paths that the rule depends on.
+    fn get_paths(&self) -> Vec<&Path> {
+        vec![]
+    }
+}
+
+impl fmt::Debug for UnsupportedRule {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "unsupported({:?})", self.message)
+    }
+}
+
+impl<'rules> Solver<'rules> {
+    /// Makes the inference fail with the given message.
+    ///
+    /// For instance, one could write:
+    /// ```text
+    /// solver.unsupported("Conv2D only supports the NHWC data format.");
+    /// ```
+    pub fn unsupported(&mut self, message: &'static str) -> &mut Solver<'rules> {
+        self.rules.push(Box::new(UnsupportedRule { message }));
+        self
+    }
+
+    /// Ensures that an integer expression is lower than or equal to another,
+    /// as soon as both their values are known.
+    ///
+    /// For instance, one could write:
+    /// ```text
+    /// solver.less_or_equal(&kernel.shape[0], &input.shape[1], "input shorter than kernel");
+    /// ```
+    pub fn less_or_equal<EA, EB, A, B>(&mut self, left: A, right: B, message: &'static str) -> &mut Solver<'rules>
+    where
+        EA: Expression<Output = IntFact> + 'static,
+        EB: Expression<Output = IntFact> + 'static,
+        A: IntoExpression<EA>,
+        B: IntoExpression<EB>,
+    {
+        let rule = LessOrEqualRule::new(Box::new(left.into_expr()), Box::new(right.into_expr()), message);
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Ensures that an integer expression divides another, as soon as both
+    /// their values are known.
+    ///
+    /// For instance, one could write:
+    /// ```text
+    /// solver.divides(&strides[1], sum(&input.shape[1], (-1, &kernel.shape[0])), "uneven strides");
+    /// ```
+    pub fn divides<EA, EB, A, B>(&mut self, divisor: A, dividend: B, message: &'static str) -> &mut Solver<'rules>
+    where
+        EA: Expression<Output = IntFact> + 'static,
+        EB: Expression<Output = IntFact> + 'static,
+        A: IntoExpression<EA>,
+        B: IntoExpression<EB>,
+    {
+        let rule = DividesRule::new(Box::new(divisor.into_expr()), Box::new(dividend.into_expr()), message);
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Adds rules for each of the inputs, once their number is known.
+    ///
+    /// This is meant for operations with a variadic number of inputs, e.g.
+    /// to ensure that all the inputs of an AddN have the same shape:
+    /// ```text
+    /// solver.for_each_input(inputs, move |solver, _, input| {
+    ///     solver.equals(&input.shape, &outputs[0].shape);
+    /// });
+    /// ```
+    pub fn for_each_input<F>(&mut self, inputs: &'rules TensorsProxy, closure: F) -> &mut Solver<'rules>
+    where
+        F: Fn(&mut Solver<'rules>, usize, &'rules TensorProxy) + 'rules,
+    {
+        self.for_each(inputs, closure)
+    }
+
+    /// Adds rules for each of the outputs, once their number is known.
+    ///
+    /// This is meant for operations with a variadic number of outputs, e.g.
+    /// to ensure that all the outputs of a Split have the same datatype.
+    pub fn for_each_output<F>(&mut self, outputs: &'rules TensorsProxy, closure: F) -> &mut Solver<'rules>
+    where
+        F: Fn(&mut Solver<'rules>, usize, &'rules TensorProxy) + 'rules,
+    {
+        self.for_each(outputs, closure)
+    }
+
+    /// Adds rules for each of the tensors of a proxy, once their number is
+    /// known.
+    fn for_each<F>(&mut self, tensors: &'rules TensorsProxy, closure: F) -> &mut Solver<'rules>
+    where
+        F: Fn(&mut Solver<'rules>, usize, &'rules TensorProxy) + 'rules,
+    {
+        self.given(&tensors.len, move |solver, len: usize| {
+            for i in 0..len {
+                closure(solver, i, &tensors[i]);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::proxies::{Side, TensorsProxy};
+    use analyser::types::{DimFact, ShapeFact, TensorFact};
+    use tfpb::types::DataType;
+
+    /// Applies `inputs[0].shape[0] <= inputs[1].shape[0]` once, and returns
+    /// whether the rule was used.
+    fn less_or_equal(left: ShapeFact, right: ShapeFact) -> Result<bool> {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.less_or_equal(&inputs[0].shape[0], &inputs[1].shape[0], "too long");
+
+        let facts = vec![
+            TensorFact { shape: left, ..TensorFact::new() },
+            TensorFact { shape: right, ..TensorFact::new() },
+        ];
+
+        let mut context = Context::new(facts, vec![]);
+        let (used, _) = solver.rules[0].apply(&mut context)?;
+        Ok(used)
+    }
+
+    #[test]
+    fn less_or_equal_concrete() {
+        assert_eq!(less_or_equal(shapefact![2], shapefact![3]).unwrap(), true);
+        assert_eq!(less_or_equal(shapefact![3], shapefact![3]).unwrap(), true);
+        assert!(less_or_equal(shapefact![4], shapefact![3]).is_err());
+    }
+
+    #[test]
+    fn less_or_equal_streamed() {
+        assert_eq!(less_or_equal(shapefact![3], shapefact![S]).unwrap(), true);
+        assert!(less_or_equal(shapefact![S], shapefact![3]).is_err());
+        assert_eq!(less_or_equal(shapefact![S], shapefact![S]).unwrap(), false);
+    }
+
+    #[test]
+    fn less_or_equal_unknown() {
+        assert_eq!(less_or_equal(shapefact![_], shapefact![3]).unwrap(), false);
+        assert_eq!(less_or_equal(shapefact![S], shapefact![_]).unwrap(), false);
+    }
+
+    /// Applies `inputs[0].shape[0] | inputs[1].shape[0]` once, and returns
+    /// whether the rule was used.
+    fn divides(divisor: ShapeFact, dividend: ShapeFact) -> Result<bool> {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.divides(&inputs[0].shape[0], &inputs[1].shape[0], "uneven");
+
+        let facts = vec![
+            TensorFact { shape: divisor, ..TensorFact::new() },
+            TensorFact { shape: dividend, ..TensorFact::new() },
+        ];
+
+        let mut context = Context::new(facts, vec![]);
+        let (used, _) = solver.rules[0].apply(&mut context)?;
+        Ok(used)
+    }
+
+    #[test]
+    fn divides_concrete() {
+        assert_eq!(divides(shapefact![3], shapefact![12]).unwrap(), true);
+        assert_eq!(divides(shapefact![3], shapefact![0]).unwrap(), true);
+        assert!(divides(shapefact![5], shapefact![12]).is_err());
+        assert!(divides(shapefact![0], shapefact![12]).is_err());
+    }
+
+    #[test]
+    fn divides_unknown() {
+        assert_eq!(divides(shapefact![_], shapefact![12]).unwrap(), false);
+        assert_eq!(divides(shapefact![3], shapefact![S]).unwrap(), false);
+    }
+
+    /// Solves the rules of an operation which, like Unpack, has one output
+    /// per row of its input, and returns the inferred outputs.
+    fn unpack(input: TensorFact) -> Vec<TensorFact> {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+        let mut solver = Solver::default();
+
+        solver
+            .equals(&outputs.len, &inputs[0].shape[0])
+            .for_each_output(&outputs, |solver, _, output| {
+                solver.equals(&output.datatype, &inputs[0].datatype);
+            });
+
+        let (_, outputs) = solver.infer((vec![input], vec![])).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn for_each_output_once_len_is_solved() {
+        let input = TensorFact {
+            datatype: typefact!(DataType::DT_FLOAT),
+            shape: shapefact![3, 2],
+            value: valuefact!(_),
+        };
+
+        let outputs = unpack(input);
+        assert_eq!(outputs.len(), 3);
+        assert!(outputs.iter().all(|o| o.datatype == typefact!(DataType::DT_FLOAT)));
+    }
+
+    #[test]
+    fn for_each_input_once_len_is_solved() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+        let mut solver = Solver::default();
+
+        // The number of inputs of a Pack along axis 0 is the first dimension
+        // of its output, so it's only known once the output shape is.
+        solver
+            .equals(&inputs.len, &outputs[0].shape[0])
+            .for_each_input(&inputs, |solver, _, input| {
+                solver
+                    .equals(&input.datatype, &outputs[0].datatype)
+                    .equals(&input.shape[0], &outputs[0].shape[1]);
+            });
+
+        let output = TensorFact {
+            datatype: typefact!(DataType::DT_INT32),
+            shape: shapefact![4, 5],
+            value: valuefact!(_),
+        };
+
+        let mut context = Context::new(vec![], vec![output]);
+        context.inputs_len = IntFact::Any;
+        Solver::solve(solver.rules, &mut context, None).unwrap();
+
+        assert_eq!(context.inputs_len, IntFact::Only(4));
+        assert_eq!(context.inputs.len(), 4);
+        for input in &context.inputs {
+            assert_eq!(input.datatype, typefact!(DataType::DT_INT32));
+            assert_eq!(input.shape.dims[0], DimFact::Only(5));
+        }
+    }
+
+    #[test]
+    fn fail_on_unsupported() {
+        let mut solver = Solver::default();
+        solver.unsupported("not supported");
+
+        assert!(solver.infer((vec![], vec![])).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use analyser::interface::path::Path;
+use analyser::interface::solver::{Context, Rule, Solver};
+use analyser::interface::tracing::{self, Application, SolverTrace};
+use Result;
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// An index of the rules of a solver by the paths they depend on.
+#[derive(Default)]
+struct RuleIndex {
+    // The rules which depend on each path.
+    by_path: BTreeMap<Path, Vec<usize>>,
+
+    // The rules which don't know their paths in advance.
+    global: Vec<usize>,
+}
+
+impl RuleIndex {
+    /// Adds a rule to the index.
+    fn insert(&mut self, rule: usize, paths: Vec<&Path>) {
+        if paths.is_empty() {
+            self.global.push(rule);
+        }
+
+        for path in paths {
+            self.by_path.entry(path.clone()).or_insert_with(Vec::new).push(rule);
+        }
+    }
+
+    /// Returns the rules which might be affected by a change of the value at
+    /// the given path, i.e. the rules which depend on that path, on one of
+    /// its prefixes (e.g. `inputs[0].shape` for `inputs[0].shape[1]`) or on
+    /// one of its extensions.
+    fn dependents(&self, path: &Path) -> Vec<usize> {
+        let mut rules = self.global.clone();
+
+        for i in 0..path.len() {
+            if let Some(r) = self.by_path.get(&path[..i]) {
+                rules.extend(r);
+            }
+        }
+
+        for (other, r) in self.by_path.range(path.clone()..) {
+            if !other.starts_with(&path[..]) {
+                break;
+            }
+
+            rules.extend(r);
+        }
+
+        rules
+    }
+}
+
+impl<'rules> Solver<'rules> {
+    /// Applies a set of rules to a context until reaching a fixed point.
+    ///
+    /// Instead of repeatedly trying all the rules, the rules are indexed by
+    /// the paths they depend on, and a rule is only tried again once one of
+    /// these paths has changed. Rules which don't declare their paths are
+    /// tried again after every change.
+    pub fn solve(
+        rules: Vec<Box<Rule<'rules> + 'rules>>,
+        context: &mut Context,
+        mut trace: Option<&mut SolverTrace>,
+    ) -> Result<()> {
+        let mut index = RuleIndex::default();
+        let mut rules: Vec<_> = rules.into_iter().map(|r| (false, r)).collect();
+        let mut queued = vec![true; rules.len()];
+        let mut queue: VecDeque<_> = (0..rules.len()).collect();
+
+        for (i, (_, rule)) in rules.iter().enumerate() {
+            index.insert(i, rule.get_paths());
+        }
+
+        context.take_changes();
+
+        while let Some(i) = queue.pop_front() {
+            queued[i] = false;
+
+            // Don't try to apply rules which have already been used.
+            if rules[i].0 {
+                continue;
+            }
+
+            trace!("  Applying rule {:?}", rules[i].1);
+            let (used, added) = match rules[i].1.apply(context) {
+                Ok(result) => result,
+                Err(e) => {
+                    // Keep the trace of the run up to the failure.
+                    if let Some(ref mut trace) = trace {
+                        trace.failure = Some((format!("{:?}", rules[i].1), format!("{}", e)));
+                        trace.unused = unused_rules(&rules);
+                    }
+
+                    return Err(e);
+                }
+            };
+
+            tracing::count_application();
+            rules[i].0 |= used;
+
+            // Wake up the rules which depend on the paths that changed.
+            let changes = context.take_changes();
+            for change in &changes {
+                for j in index.dependents(&change.path) {
+                    if !rules[j].0 && !queued[j] {
+                        queued[j] = true;
+                        queue.push_back(j);
+                    }
+                }
+            }
+
+            if let Some(ref mut trace) = trace {
+                trace.applications.push(Application {
+                    rule: format!("{:?}", rules[i].1),
+                    used,
+                    added: added.len(),
+                    changes,
+                });
+            }
+
+            for rule in added {
+                let j = rules.len();
+                index.insert(j, rule.get_paths());
+                rules.push((false, rule));
+                queued.push(true);
+                queue.push_back(j);
+            }
+        }
+
+        if let Some(trace) = trace {
+            trace.unused = unused_rules(&rules);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the rules which haven't been used yet.
+fn unused_rules<'rules>(rules: &[(bool, Box<Rule<'rules> + 'rules>)]) -> Vec<String> {
+    rules
+        .iter()
+        .filter(|(used, _)| !used)
+        .map(|(_, rule)| format!("{:?}", rule))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::proxies::{Side, TensorsProxy};
+    use analyser::types::TensorFact;
+
+    #[test]
+    fn dependents_of_path() {
+        let mut index = RuleIndex::default();
+        index.insert(0, vec![&vec![0, 0, 2].into()]);
+        index.insert(1, vec![&vec![0, 0, 2, 1].into()]);
+        index.insert(2, vec![&vec![0, 0].into()]);
+        index.insert(3, vec![&vec![0, 0, 1].into()]);
+        index.insert(4, vec![]);
+
+        let mut dependents = index.dependents(&vec![0, 0, 2].into());
+        dependents.sort();
+        assert_eq!(dependents, vec![0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn setting_rank_wakes_rules_on_shape() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+
+        // The rule on the shapes is applied first, when nothing is known.
+        let mut solver = Solver::default();
+        solver
+            .equals(&outputs[0].shape, &inputs[0].shape)
+            .equals(&inputs[0].rank, 2);
+
+        let (_, outputs) = solver.infer((vec![TensorFact::new()], vec![TensorFact::new()])).unwrap();
+        assert_eq!(outputs[0].shape, shapefact![_, _]);
+    }
+}
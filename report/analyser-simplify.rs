@@ -0,0 +1,175 @@
+use super::prelude::*;
+use super::Result;
+use std::collections::HashMap;
+use Tensor;
+
+/// The result of the simplification of a graph.
+#[derive(Debug)]
+pub struct Simplification {
+    /// The number of bypassed nodes for each operation name.
+    pub removed: HashMap<String, usize>,
+
+    /// The mapping between the old and new node indexes.
+    pub mapping: Vec<Option<usize>>,
+}
+
+/// Returns the fact about the first output of a node, if it has any successor.
+fn output_fact(analyser: &Analyser, node: usize) -> Option<&TensorFact> {
+    analyser.next_edges[node]
+        .iter()
+        .map(|&j| &analyser.edges[j])
+        .find(|e| e.from_out == 0)
+        .map(|e| &e.fact)
+}
+
+/// Returns the fact about a given input of a node.
+fn input_fact(analyser: &Analyser, node: usize, port: usize) -> Option<&TensorFact> {
+    analyser.prev_edges[node]
+        .get(port)
+        .map(|&j| &analyser.edges[j].fact)
+}
+
+/// Returns whether a permutation leaves every axis in place.
+///
+/// The permutations of TensorFlow can be stored in any integer type.
+fn is_identity_permutation(perm: &Tensor) -> bool {
+    let perm: Vec<i64> = match perm {
+        Tensor::I32(p) => p.iter().map(|&v| v as i64).collect(),
+        Tensor::I8(p) => p.iter().map(|&v| v as i64).collect(),
+        Tensor::U8(p) => p.iter().map(|&v| v as i64).collect(),
+        _ => return false,
+    };
+
+    perm.iter().enumerate().all(|(i, &p)| p == i as i64)
+}
+
+/// Returns whether the analyser proved that a node just forwards its first
+/// input, in which case the node can be bypassed.
+fn is_useless(analyser: &Analyser, node: usize) -> bool {
+    let (input, output) = match (input_fact(analyser, node, 0), output_fact(analyser, node)) {
+        (Some(i), Some(o)) => (i, o),
+        _ => return false,
+    };
+
+    match analyser.nodes[node].op_name.as_str() {
+        "Identity" | "StopGradient" | "Snapshot" => true,
+
+        "Reshape" | "Squeeze" | "ExpandDims" => {
+            match (input.shape.concretize(), output.shape.concretize()) {
+                (Some(i), Some(o)) => i == o,
+                _ => false,
+            }
+        }
+
+        "Transpose" => {
+            input_fact(analyser, node, 1)
+                .and_then(|f| f.value.concretize())
+                .map(|perm| is_identity_permutation(&perm))
+                .unwrap_or(false)
+        }
+
+        "Cast" => match (input.datatype.concretize(), output.datatype.concretize()) {
+            (Some(i), Some(o)) => i == o,
+            _ => false,
+        },
+
+        _ => false,
+    }
+}
+
+/// Removes the nodes which the analyser proved useless from the graph.
+///
+/// This bypasses the nodes which just forward their first input, e.g. an
+/// Identity or a Reshape to the shape of its input, and then removes them
+/// along with all the nodes that don't contribute to the output anymore.
+/// The analysis should have reached a fixed point before calling this.
+pub fn simplify(analyser: &mut Analyser) -> Result<Simplification> {
+    let mut removed = HashMap::new();
+
+    for node in 0..analyser.nodes.len() {
+        if node == analyser.output || !is_useless(analyser, node) || !analyser.can_bypass(node, 0) {
+            continue;
+        }
+
+        debug!(
+            "Bypassing {} ({}).",
+            analyser.nodes[node].name, analyser.nodes[node].op_name
+        );
+
+        analyser.bypass(node, 0)?;
+        *removed.entry(analyser.nodes[node].op_name.clone()).or_insert(0) += 1;
+    }
+
+    analyser.reset_plan()?;
+
+    info!("Simplification removed {:?}.", removed);
+    let mapping = analyser.prune_unused();
+
+    Ok(Simplification { removed, mapping })
+}
+
+impl Analyser {
+    /// Removes the nodes which the analysis proved useless, along with the
+    /// nodes that don't contribute to the output.
+    pub fn simplify(&mut self) -> Result<Simplification> {
+        simplify(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr1;
+    use tfpb;
+    use tfpb::types::DataType::{DT_FLOAT, DT_INT32};
+    use Model;
+
+    /// Builds `Relu(Transpose(Identity(input), perm))`.
+    fn graph(perm: &[i32]) -> tfpb::graph::GraphDef {
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(tfpb::node().name("identity".to_string()).op("Identity").attr("T", DT_FLOAT)
+                .input("input".to_string()))
+            .node(tfpb::node().name("perm".to_string()).op("Const").attr("dtype", DT_INT32)
+                .attr("value", Tensor::I32(arr1(perm).into_dyn()).to_pb().unwrap()))
+            .node(tfpb::node().name("transpose".to_string()).op("Transpose").attr("T", DT_FLOAT)
+                .input("identity".to_string()).input("perm".to_string()))
+            .node(tfpb::node().name("relu".to_string()).op("Relu").attr("T", DT_FLOAT)
+                .input("transpose".to_string()))
+    }
+
+    fn simplified(perm: &[i32]) -> (Simplification, Model) {
+        let model = Model::new(graph(perm)).unwrap();
+        let output = model.nodes_by_name["relu"];
+
+        let mut analyser = Analyser::new(model, output).unwrap();
+        analyser.run().unwrap();
+
+        (analyser.simplify().unwrap(), analyser.into_model())
+    }
+
+    #[test]
+    fn bypass_identity_and_noop_transpose() {
+        let (simplification, model) = simplified(&[0, 1]);
+
+        assert_eq!(simplification.removed.get("Identity"), Some(&1));
+        assert_eq!(simplification.removed.get("Transpose"), Some(&1));
+        assert_eq!(simplification.removed.len(), 2);
+
+        assert!(!model.nodes_by_name.contains_key("identity"));
+        assert!(!model.nodes_by_name.contains_key("transpose"));
+        assert!(!model.nodes_by_name.contains_key("perm"));
+
+        let relu = &model.nodes[model.nodes_by_name["relu"]];
+        assert_eq!(relu.inputs, vec![(model.nodes_by_name["input"], Some(0))]);
+    }
+
+    #[test]
+    fn keep_actual_transpose() {
+        let (simplification, model) = simplified(&[1, 0]);
+
+        assert_eq!(simplification.removed.get("Identity"), Some(&1));
+        assert_eq!(simplification.removed.get("Transpose"), None);
+        assert!(model.nodes_by_name.contains_key("transpose"));
+    }
+}
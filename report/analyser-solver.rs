@@ -1,35 +1,63 @@
 use analyser::interface::expressions::Expression;
 use analyser::interface::expressions::IntoExpression;
 use analyser::interface::expressions::Output;
-use analyser::interface::path::{get_path, set_path, Path};
+use analyser::interface::expressions::Wrapped;
+use analyser::interface::path::Path;
+use analyser::interface::tracing::{self, SolverTrace};
 use analyser::types::{Fact, IntFact, SpecialKind, TensorFact};
 use Result;
 
 use std::fmt;
+use std::mem;
+
+/// A change of the value at a given path of the context.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub path: Path,
+    pub before: Wrapped,
+    pub after: Wrapped,
+}
 
 /// A structure that holds the current sets of TensorFacts.
 ///
 /// This is used during inference (see `Solver::infer`) to let rules compute
-/// the value of expressions which involve tensor properties.
-#[derive(Debug, new)]
+/// the value of expressions which involve tensor properties. The number of
+/// inputs and outputs are facts as well, to support variadic operations.
+#[derive(Debug, Clone)]
 pub struct Context {
     pub inputs: Vec<TensorFact>,
     pub outputs: Vec<TensorFact>,
+    pub inputs_len: IntFact,
+    pub outputs_len: IntFact,
+
+    // The changes made to the context since the last call to `take_changes`.
+    changes: Vec<Change>,
 }
 
 impl Context {
-    /// Returns the current value of the variable at the given path.
-    pub fn get<T: Output>(&self, path: &Path) -> Result<T> {
-        let value = get_path(self, &path[..])?;
-
-        Ok(T::from_wrapped(value)?)
+    /// Creates a new Context instance.
+    ///
+    /// The caller always knows all the inputs of an operation, but it might
+    /// only know some of its outputs, so the number of outputs is unknown.
+    pub fn new(inputs: Vec<TensorFact>, outputs: Vec<TensorFact>) -> Context {
+        Context {
+            inputs_len: IntFact::Only(inputs.len() as isize),
+            outputs_len: IntFact::Any,
+            inputs,
+            outputs,
+            changes: vec![],
+        }
     }
 
-    /// Tries to set the value of the variable at the given path.
-    pub fn set<T: Output>(&mut self, path: &Path, value: T) -> Result<()> {
-        set_path(self, &path[..], T::into_wrapped(value))?;
+    /// Records a change made to the context, so that the rules which depend
+    /// on the path are applied again.
+    pub fn record(&mut self, path: Path, before: Wrapped, after: Wrapped) {
+        self.changes.push(Change { path, before, after });
+    }
 
-        Ok(())
+    /// Returns the changes made to the context since the last call.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        mem::replace(&mut self.changes, vec![])
     }
 }
 
@@ -83,11 +111,18 @@ impl<'rules, T: Output + Fact> Rule<'rules> for EqualsRule<T> {
             for item in &self.items {
                 item.set(context, value.clone())?;
             }
+        }
 
-            Ok((true, vec![]))
-        } else {
-            Ok((false, vec![]))
+        // Some expressions, e.g. products, can't always deduce their operands
+        // from their value, so the rule must be applied again until all the
+        // values are known.
+        for item in &self.items {
+            if !item.get(context)?.is_concrete() {
+                return Ok((false, vec![]));
+            }
         }
+
+        Ok((true, vec![]))
     }
 
     /// Returns the paths that the rule depends on.
@@ -278,42 +313,22 @@ impl<'rules> Solver<'rules> {
     /// - Err(_) if a constraint couldn't be satisfied.
     /// - Ok(None) if no more information about tensors could be deduced.
     /// - Ok(Some(facts)) otherwise, with `facts` the new TensorFacts.
+    ///
+    /// The trace of the run is also recorded if tracing was started.
     pub fn infer(
         self,
         facts: (Vec<TensorFact>, Vec<TensorFact>),
     ) -> Result<(Vec<TensorFact>, Vec<TensorFact>)> {
         let mut context = Context::new(facts.0, facts.1);
+        let mut trace = if tracing::is_tracing() { Some(SolverTrace::default()) } else { None };
 
-        // Apply the rules until reaching a fixed point.
-        let mut changed = true;
-        let mut added_rules = vec![];
-        let mut rules: Vec<_> = self.rules.into_iter().map(|r| (false, r)).collect();
-
-        while changed {
-            changed = false;
-
-            for (used, rule) in &mut rules {
-                // Don't try to apply rules which have already been used.
-                if *used {
-                    continue;
-                }
-
-                trace!("  Applying rule {:?}", rule);
-                let (step_used, mut step_added) = rule.apply(&mut context)?;
-                *used |= step_used;
-
-                // There is a change if the rule was used, or if it added new rules.
-                changed |= step_used;
-                changed |= step_added.len() > 0;
-
-                added_rules.append(&mut step_added);
-            }
-
-            for rule in added_rules.drain(..) {
-                rules.push((false, rule));
-            }
+        // The trace is recorded even if the run fails.
+        let result = Solver::solve(self.rules, &mut context, trace.as_mut());
+        if let Some(trace) = trace {
+            tracing::push_trace(trace);
         }
 
+        result?;
         Ok((context.inputs, context.outputs))
     }
 
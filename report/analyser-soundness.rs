@@ -0,0 +1,264 @@
+use super::prelude::*;
+use super::Result;
+use ndarray::prelude::*;
+use ops::{Op, OpBuilder};
+use tfpb;
+use tfpb::node_def::NodeDef;
+use tfpb::types::DataType;
+use Tensor;
+
+/// The description of an input of an operation.
+#[derive(Debug, Clone)]
+pub enum InputSpec {
+    // The input is generated randomly with the given datatype and shape.
+    Random(DataType, Vec<usize>),
+
+    // The input must have a specific value, e.g. the shape of a Reshape.
+    Constant(Tensor),
+}
+
+/// A case to check, i.e. an operation along with the inputs to give it.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub node: NodeDef,
+    pub inputs: Vec<InputSpec>,
+}
+
+impl Case {
+    /// Creates a new case for the given operation and inputs.
+    pub fn new(node: NodeDef, inputs: Vec<InputSpec>) -> Case {
+        Case { node, inputs }
+    }
+}
+
+/// Checks that the facts inferred by an operation are compatible with the
+/// result of its evaluation on the given inputs.
+///
+/// Two directions are checked:
+/// - forwards, the facts inferred from the actual inputs alone must unify
+///   with the actual inputs and outputs;
+/// - backwards, the facts inferred from the actual outputs alone must unify
+///   with the actual inputs and outputs.
+///
+/// Note that this only checks soundness: rules which don't infer anything
+/// will always pass.
+pub fn check_op(name: &str, op: &Op, inputs: Vec<Tensor>) -> Result<()> {
+    let outputs: Vec<Tensor> = op
+        .eval(inputs.iter().cloned().map(|t| t.into()).collect())?
+        .into_iter()
+        .map(|t| t.into_tensor())
+        .collect();
+
+    let input_facts: Vec<_> = inputs.iter().cloned().map(tensor_to_fact).collect();
+    let output_facts: Vec<_> = outputs.iter().cloned().map(tensor_to_fact).collect();
+
+    let check = |direction: &str, enriched: (Vec<TensorFact>, Vec<TensorFact>)| -> Result<()> {
+        let actual = input_facts.iter().zip(&enriched.0).chain(output_facts.iter().zip(&enriched.1));
+
+        for (i, (actual, inferred)) in actual.enumerate() {
+            if let Err(e) = unify(actual, inferred) {
+                bail!(
+                    "Unsound {} rules for {} on tensor #{}: inferred {:?} but found {:?} ({}).",
+                    direction, name, i, inferred, actual, e
+                );
+            }
+        }
+
+        Ok(())
+    };
+
+    let forward = op.enrich(input_facts.clone(), vec![TensorFact::new(); outputs.len()])?;
+    check("forward", forward)?;
+
+    let backward = op.enrich(vec![TensorFact::new(); inputs.len()], output_facts.clone())?;
+    check("backward", backward)?;
+
+    Ok(())
+}
+
+/// Checks the rules of the operation of a case on random inputs.
+pub fn check_case(case: &Case, iterations: usize) -> Result<()> {
+    let op = OpBuilder::new().build(&case.node)?;
+
+    for _ in 0..iterations {
+        let inputs = case
+            .inputs
+            .iter()
+            .map(|spec| match spec {
+                InputSpec::Random(dt, shape) => Tensor::random(*dt, shape),
+                InputSpec::Constant(tensor) => Ok(tensor.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        check_op(case.node.get_name(), &*op, inputs.clone())
+            .map_err(|e| format!("{} (with inputs {:?})", e, inputs))?;
+    }
+
+    Ok(())
+}
+
+/// The operations which aren't checked by `check_all`, because they have no
+/// inputs to generate.
+pub const UNCHECKED: &[&str] = &["Const", "Placeholder"];
+
+/// Returns the cases which are checked by `check_all`.
+///
+/// Operations can't describe their inputs, so every registered operation
+/// which isn't in `UNCHECKED` needs a case here, otherwise `check_all` fails.
+pub fn default_cases() -> Vec<Case> {
+    use self::InputSpec::*;
+    use tfpb::types::DataType::*;
+
+    let f32_node = |op: &str| tfpb::node().name(op.to_string()).op(op).attr("T", DT_FLOAT);
+    let i32 = |v: i32| Constant(Tensor::I32(arr0(v).into_dyn()));
+    let i32s = |v: &[i32]| Constant(Tensor::I32(arr1(v).into_dyn()));
+
+    let mut cases = vec![];
+
+    for op in &["Add", "Sub", "Mul", "Div", "Maximum", "Minimum", "SquaredDifference"] {
+        cases.push(Case::new(f32_node(op), vec![Random(DT_FLOAT, vec![2, 3]), Random(DT_FLOAT, vec![2, 3])]));
+        cases.push(Case::new(f32_node(op), vec![Random(DT_FLOAT, vec![4, 2, 3]), Random(DT_FLOAT, vec![3])]));
+        cases.push(Case::new(f32_node(op), vec![Random(DT_FLOAT, vec![2, 1]), Random(DT_FLOAT, vec![1, 5])]));
+    }
+
+    for op in &["Identity", "StopGradient", "Relu", "Sigmoid", "Tanh"] {
+        cases.push(Case::new(f32_node(op), vec![Random(DT_FLOAT, vec![3, 4])]));
+    }
+
+    for op in &["Shape", "Size"] {
+        let node = f32_node(op).attr("out_type", DT_INT32);
+        cases.push(Case::new(node, vec![Random(DT_FLOAT, vec![2, 3, 4])]));
+    }
+
+    cases.push(Case::new(f32_node("Rank"), vec![Random(DT_FLOAT, vec![2, 3, 4])]));
+
+    cases.push(Case::new(
+        f32_node("Reshape").attr("Tshape", DT_INT32),
+        vec![Random(DT_FLOAT, vec![2, 3, 4]), i32s(&[6, -1])],
+    ));
+
+    cases.push(Case::new(
+        f32_node("Pad").attr("Tpaddings", DT_INT32),
+        vec![Random(DT_FLOAT, vec![2, 3]), Constant(Tensor::I32(arr2(&[[1, 0], [2, 3]]).into_dyn()))],
+    ));
+
+    for padding in &["VALID", "SAME"] {
+        let node = f32_node("Conv2D")
+            .attr("padding", padding.to_string())
+            .attr("strides", vec![1, 2, 1, 1]);
+
+        cases.push(Case::new(node, vec![Random(DT_FLOAT, vec![1, 7, 6, 2]), Random(DT_FLOAT, vec![3, 2, 2, 4])]));
+    }
+
+    for padding in &["VALID", "SAME"] {
+        for op in &["MaxPool", "AvgPool"] {
+            let node = f32_node(op)
+                .attr("padding", padding.to_string())
+                .attr("ksize", vec![1, 3, 2, 1])
+                .attr("strides", vec![1, 2, 2, 1]);
+
+            cases.push(Case::new(node, vec![Random(DT_FLOAT, vec![1, 7, 8, 2])]));
+        }
+    }
+
+    cases.push(Case::new(
+        f32_node("BiasAdd"),
+        vec![Random(DT_FLOAT, vec![2, 3, 4]), Random(DT_FLOAT, vec![4])],
+    ));
+
+    // The variance must be positive, otherwise the output is NaN.
+    cases.push(Case::new(
+        f32_node("FusedBatchNorm").attr("is_training", false).attr("epsilon", 0.001f32),
+        vec![
+            Random(DT_FLOAT, vec![1, 2, 3, 4]),
+            Random(DT_FLOAT, vec![4]),
+            Random(DT_FLOAT, vec![4]),
+            Random(DT_FLOAT, vec![4]),
+            Constant(Tensor::F32(arr1(&[1., 0.5, 2., 0.25]).into_dyn())),
+        ],
+    ));
+
+    cases.push(Case::new(
+        tfpb::node().name("Cast".to_string()).op("Cast").attr("SrcT", DT_INT32).attr("DstT", DT_FLOAT),
+        vec![Random(DT_INT32, vec![2, 3])],
+    ));
+
+    cases.push(Case::new(
+        f32_node("Transpose").attr("Tperm", DT_INT32),
+        vec![Random(DT_FLOAT, vec![2, 3, 4]), i32s(&[2, 0, 1])],
+    ));
+
+    cases.push(Case::new(f32_node("Squeeze"), vec![Random(DT_FLOAT, vec![1, 3, 1])]));
+    cases.push(Case::new(
+        f32_node("Squeeze").attr("squeeze_dims", vec![1]),
+        vec![Random(DT_FLOAT, vec![2, 1, 3])],
+    ));
+
+    cases.push(Case::new(
+        f32_node("ExpandDims").attr("Tdim", DT_INT32),
+        vec![Random(DT_FLOAT, vec![2, 3]), i32(1)],
+    ));
+
+    cases.push(Case::new(
+        f32_node("StridedSlice").attr("Index", DT_INT32),
+        vec![Random(DT_FLOAT, vec![4, 5]), i32s(&[1, 0]), i32s(&[3, 5]), i32s(&[1, 2])],
+    ));
+
+    cases.push(Case::new(
+        f32_node("StridedSlice").attr("Index", DT_INT32).attr("shrink_axis_mask", 1),
+        vec![Random(DT_FLOAT, vec![4, 5]), i32s(&[1, 0]), i32s(&[2, 5]), i32s(&[1, 1])],
+    ));
+
+    cases.push(Case::new(
+        f32_node("Pack").attr("N", 3).attr("axis", 1),
+        vec![Random(DT_FLOAT, vec![2, 3]); 3],
+    ));
+
+    cases.push(Case::new(
+        f32_node("ConcatV2").attr("N", 2).attr("Tidx", DT_INT32),
+        vec![Random(DT_FLOAT, vec![2, 3]), Random(DT_FLOAT, vec![2, 5]), i32(1)],
+    ));
+
+    cases.push(Case::new(
+        f32_node("Range").attr("Tidx", DT_INT32),
+        vec![i32(2), i32(11), i32(3)],
+    ));
+
+    cases.push(Case::new(
+        f32_node("Gather").attr("Tparams", DT_INT32).attr("Tindices", DT_INT32),
+        vec![Random(DT_INT32, vec![5]), i32s(&[4, 0, 2])],
+    ));
+
+    cases
+}
+
+/// Checks the rules of all the operations in `default_cases`, and returns
+/// the errors found for each case, along with the registered operations
+/// which have no case.
+pub fn check_all(iterations: usize) -> Vec<String> {
+    let cases = default_cases();
+
+    let missing = OpBuilder::new()
+        .names()
+        .into_iter()
+        .filter(|name| !UNCHECKED.contains(name))
+        .filter(|name| cases.iter().all(|c| c.node.get_op() != *name))
+        .map(|name| format!("No soundness case for operation {}.", name));
+
+    let errors = cases
+        .iter()
+        .filter_map(|case| check_case(case, iterations).err())
+        .map(|e| format!("{}", e));
+
+    missing.chain(errors).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_ops_are_sound() {
+        assert_eq!(check_all(20), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,236 @@
+use analyser::interface::solver::{Change, Context, Solver};
+use analyser::Analyser;
+use analyser::types::TensorFact;
+use {Node, Result};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+
+thread_local! {
+    /// The number of rules applied by the solvers of the current thread.
+    static APPLICATIONS: Cell<usize> = Cell::new(0);
+
+    /// The traces of the solvers of the current thread, if tracing is on.
+    static TRACES: RefCell<Option<Vec<SolverTrace>>> = RefCell::new(None);
+}
+
+/// Returns the number of rules applied by the solvers of the current thread
+/// so far, which lets callers measure the cost of a given inference.
+pub fn applications() -> usize {
+    APPLICATIONS.with(|a| a.get())
+}
+
+/// Starts recording the traces of the solvers run by the current thread.
+pub fn start_tracing() {
+    TRACES.with(|t| *t.borrow_mut() = Some(vec![]));
+}
+
+/// Stops recording traces, and returns the traces of the solvers which were
+/// run by the current thread since the call to `start_tracing`.
+pub fn stop_tracing() -> Vec<SolverTrace> {
+    TRACES.with(|t| t.borrow_mut().take()).unwrap_or_default()
+}
+
+/// Records that a solver of the current thread applied a rule.
+pub fn count_application() {
+    APPLICATIONS.with(|a| a.set(a.get() + 1));
+}
+
+/// Returns whether the traces of the solvers are recorded on this thread.
+pub fn is_tracing() -> bool {
+    TRACES.with(|t| t.borrow().is_some())
+}
+
+/// Records the trace of a run of a solver, if tracing is on.
+pub fn push_trace(trace: SolverTrace) {
+    TRACES.with(|t| t.borrow_mut().as_mut().map(|traces| traces.push(trace)));
+}
+
+/// A single application of a rule during a run of the solver.
+#[derive(Debug, Clone)]
+pub struct Application {
+    /// The rule which was applied.
+    pub rule: String,
+
+    /// Whether the rule was used, and so won't be applied again.
+    pub used: bool,
+
+    /// The number of rules that the rule added to the solver.
+    pub added: usize,
+
+    /// The changes that the rule made to the context.
+    pub changes: Vec<Change>,
+}
+
+/// A structured trace of a run of the solver.
+#[derive(Debug, Clone, Default)]
+pub struct SolverTrace {
+    /// The applications of rules, in the order in which they happened.
+    pub applications: Vec<Application>,
+
+    /// The rules which were never used.
+    pub unused: Vec<String>,
+
+    /// The rule which made the run fail, along with the error, if any.
+    pub failure: Option<(String, String)>,
+}
+
+impl fmt::Display for SolverTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, application) in self.applications.iter().enumerate() {
+            let status = if application.used { "used" } else { "not used" };
+            writeln!(f, "#{} {} ({}, added {} rules)", i, application.rule, status, application.added)?;
+
+            for change in &application.changes {
+                writeln!(f, "    {:?}: {:?} -> {:?}", change.path, change.before, change.after)?;
+            }
+        }
+
+        if let Some((rule, error)) = &self.failure {
+            writeln!(f, "Failed while applying {}: {}", rule, error)?;
+        }
+
+        writeln!(f, "Rules never used:")?;
+        for rule in &self.unused {
+            writeln!(f, "    {}", rule)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'rules> Solver<'rules> {
+    /// Runs the solver on a set of TensorFacts, and also returns a trace of
+    /// the rules which were applied and of the changes they made.
+    ///
+    /// The trace is returned even if the run fails, in which case it ends
+    /// with the rule which failed.
+    pub fn infer_with_trace(
+        self,
+        facts: (Vec<TensorFact>, Vec<TensorFact>),
+    ) -> (Result<(Vec<TensorFact>, Vec<TensorFact>)>, SolverTrace) {
+        let mut context = Context::new(facts.0, facts.1);
+        let mut trace = SolverTrace::default();
+
+        let result = Solver::solve(self.rules, &mut context, Some(&mut trace))
+            .map(|()| (context.inputs, context.outputs));
+
+        (result, trace)
+    }
+}
+
+/// The number of rules applied by the solvers for each operation name, and
+/// the traces of the solver runs for the nodes which are traced.
+#[derive(Debug, Clone, Default)]
+pub struct SolverStats {
+    pub applications: HashMap<String, usize>,
+
+    // The traces of the latest step of each traced node.
+    pub traces: HashMap<usize, Vec<SolverTrace>>,
+}
+
+impl SolverStats {
+    /// Enriches the facts about the inputs and outputs of a node, while
+    /// counting the rules applied and tracing the solver runs if needed.
+    pub fn enrich(
+        &mut self,
+        node: &Node,
+        inputs: Vec<TensorFact>,
+        outputs: Vec<TensorFact>,
+    ) -> Result<(Vec<TensorFact>, Vec<TensorFact>)> {
+        let traced = self.traces.contains_key(&node.id);
+        if traced {
+            start_tracing();
+        }
+
+        let before = applications();
+        let enriched = node.op.enrich(inputs, outputs);
+        *self.applications.entry(node.op_name.clone()).or_insert(0) += applications() - before;
+
+        if traced {
+            self.traces.insert(node.id, stop_tracing());
+        }
+
+        enriched
+    }
+}
+
+impl Analyser {
+    /// Starts tracing the solver runs for the given node.
+    ///
+    /// After each step of the analysis on that node, the traces of the solver
+    /// runs of that step are available in `stats.traces`.
+    pub fn trace(&mut self, node: usize) -> Result<()> {
+        if node >= self.nodes.len() {
+            bail!("There is no node with index {:?}.", node);
+        }
+
+        self.stats.traces.insert(node, vec![]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::interface::path::Path;
+    use analyser::interface::proxies::{Side, TensorsProxy};
+    use tfpb::types::DataType;
+
+    /// Runs the rules of an Identity-like operation on f32 tensors.
+    fn identity(input: TensorFact) -> (Result<(Vec<TensorFact>, Vec<TensorFact>)>, SolverTrace) {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let outputs = TensorsProxy::new(Side::Outputs);
+
+        let mut solver = Solver::default();
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&inputs[0].datatype, DataType::DT_FLOAT)
+            .equals(&outputs[0].datatype, &inputs[0].datatype)
+            .equals(&outputs[0].shape, &inputs[0].shape);
+
+        solver.infer_with_trace((vec![input], vec![TensorFact::new()]))
+    }
+
+    #[test]
+    fn trace_successful_run() {
+        let input = TensorFact { shape: shapefact![2, 3], ..TensorFact::new() };
+        let (result, trace) = identity(input);
+
+        let (_, outputs) = result.unwrap();
+        assert_eq!(outputs[0].shape, shapefact![2, 3]);
+
+        assert!(trace.failure.is_none());
+        assert!(trace.applications.len() >= 5);
+        let shape: Path = vec![1, 0, 2].into();
+        assert!(trace.applications.iter().any(|a| a.changes.iter().any(|c| c.path == shape)));
+    }
+
+    #[test]
+    fn trace_failed_run() {
+        let input = TensorFact { datatype: typefact!(DataType::DT_INT32), ..TensorFact::new() };
+        let (result, trace) = identity(input);
+
+        assert!(result.is_err());
+        assert!(trace.failure.is_some());
+        assert!(!trace.unused.is_empty());
+    }
+
+    #[test]
+    fn push_traces_of_failed_runs() {
+        let inputs = TensorsProxy::new(Side::Inputs);
+        let mut solver = Solver::default();
+        solver.equals(&inputs[0].datatype, DataType::DT_FLOAT);
+
+        let input = TensorFact { datatype: typefact!(DataType::DT_INT32), ..TensorFact::new() };
+
+        start_tracing();
+        assert!(solver.infer((vec![input], vec![])).is_err());
+        let traces = stop_tracing();
+
+        assert_eq!(traces.len(), 1);
+        assert!(traces[0].failure.is_some());
+    }
+}
@@ -0,0 +1,305 @@
+use super::constants::{connected_components, Element};
+use super::prelude::*;
+use super::Analyser;
+use super::Result;
+use profile::Profile;
+use std::collections::{BTreeMap, HashMap};
+
+/// The version of the format, which is incremented on breaking changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The maximum number of elements of the values written in the facts.
+pub const MAX_ELEMENTS: usize = 16;
+
+/// The profile of a node, in seconds.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualProfile {
+    pub real_median: f64,
+    pub real_p90: f64,
+    pub user_median: f64,
+    pub sys_median: f64,
+}
+
+/// A node of the graph.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualNode {
+    pub id: usize,
+    pub name: String,
+    pub op: String,
+
+    // The innermost name scope of the node, if any.
+    pub group: Option<String>,
+
+    // The constant component which contains the node, if any.
+    pub component: Option<usize>,
+
+    pub profile: Option<VisualProfile>,
+}
+
+/// An edge of the graph, along with the fact inferred by the analyser.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualEdge {
+    pub id: usize,
+    pub from_node: Option<usize>,
+    pub from_out: usize,
+    pub to_node: Option<usize>,
+
+    // The fact as given by `TensorFact::summary`, i.e. in the notation of
+    // the text format with large values elided, along with its datatype
+    // and dimensions in the same notation.
+    pub fact: String,
+    pub datatype: String,
+    pub shape: Vec<String>,
+    pub open: bool,
+
+    pub streamed: bool,
+    pub constant: bool,
+    pub component: Option<usize>,
+}
+
+/// A name-scope group.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualGroup {
+    pub name: String,
+    pub parent: Option<String>,
+    pub nodes: Vec<usize>,
+}
+
+/// A connected component of the constant part of the graph.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualComponent {
+    pub id: usize,
+    pub nodes: Vec<usize>,
+    pub edges: Vec<usize>,
+    pub outputs: Vec<usize>,
+}
+
+/// An analysed graph, in the JSON format read by the visualizer.
+///
+/// The format is versioned with `FORMAT_VERSION`, which is incremented for
+/// every change that could break an existing frontend. A document looks
+/// like this:
+/// ```text
+/// {
+///   "version": 1,
+///   "nodes": [{"id": 0, "name": "conv/weights", "op": "Const",
+///              "group": "conv", "component": 0, "profile": null}, ...],
+///   "edges": [{"id": 0, "from_node": 0, "from_out": 0, "to_node": 2,
+///              "fact": "f32[3,3,1,8]=...", "datatype": "f32",
+///              "shape": ["3", "3", "1", "8"], "open": false,
+///              "streamed": false, "constant": true, "component": 0}, ...],
+///   "groups": [{"name": "conv", "parent": null, "nodes": [0, 1, 2]}, ...],
+///   "components": [{"id": 0, "nodes": [0], "edges": [0], "outputs": [0]}]
+/// }
+/// ```
+///
+/// Node names are split on `/` to build the name-scope groups, so the node
+/// `conv/weights` belongs to the group `conv`. Groups list the nodes which
+/// they directly contain, and nested groups point to their parent.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct VisualGraph {
+    pub version: u32,
+    pub nodes: Vec<VisualNode>,
+    pub edges: Vec<VisualEdge>,
+    pub groups: Vec<VisualGroup>,
+    pub components: Vec<VisualComponent>,
+}
+
+impl VisualGraph {
+    /// Exports the graph as JSON.
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(::serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Returns the name scopes of a node name, from the outermost to the
+/// innermost, e.g. `["a", "a/b"]` for `a/b/c`.
+pub fn scopes(name: &str) -> Vec<String> {
+    let parts: Vec<_> = name.split('/').collect();
+    (1..parts.len()).map(|i| parts[..i].join("/")).collect()
+}
+
+/// Builds the visual representation of an analysed graph.
+///
+/// The profile is optional, and is matched with the nodes by name, so it
+/// can come from a model built from the analyser or from the original one.
+pub fn visualize(analyser: &Analyser, profile: Option<&Profile>) -> Result<VisualGraph> {
+    let components: Vec<_> = connected_components(analyser)?
+        .into_iter()
+        .enumerate()
+        .map(|(id, component)| {
+            let mut visual = VisualComponent { id, nodes: vec![], edges: vec![], outputs: component.outputs };
+
+            for element in component.elements {
+                match element {
+                    Element::Node(n) => visual.nodes.push(n),
+                    Element::Edge(e) => visual.edges.push(e),
+                }
+            }
+
+            visual
+        })
+        .collect();
+
+    let mut node_component = HashMap::new();
+    let mut edge_component = HashMap::new();
+    for component in &components {
+        node_component.extend(component.nodes.iter().map(|&n| (n, component.id)));
+        edge_component.extend(component.edges.iter().map(|&e| (e, component.id)));
+    }
+
+    let profiles: HashMap<_, _> = profile
+        .map(|p| p.nodes.iter().map(|n| (n.name.clone(), n)).collect())
+        .unwrap_or_default();
+
+    let mut groups: BTreeMap<String, VisualGroup> = BTreeMap::new();
+
+    let nodes = analyser
+        .nodes
+        .iter()
+        .map(|node| {
+            let scopes = scopes(&node.name);
+
+            for (i, scope) in scopes.iter().enumerate() {
+                groups.entry(scope.clone()).or_insert_with(|| VisualGroup {
+                    name: scope.clone(),
+                    parent: if i > 0 { Some(scopes[i - 1].clone()) } else { None },
+                    nodes: vec![],
+                });
+            }
+
+            if let Some(scope) = scopes.last() {
+                groups.get_mut(scope).unwrap().nodes.push(node.id);
+            }
+
+            VisualNode {
+                id: node.id,
+                name: node.name.clone(),
+                op: node.op_name.clone(),
+                group: scopes.last().cloned(),
+                component: node_component.get(&node.id).cloned(),
+                profile: profiles.get(&node.name).map(|p| VisualProfile {
+                    real_median: p.times.real.median,
+                    real_p90: p.times.real.p90,
+                    user_median: p.times.user.median,
+                    sys_median: p.times.sys.median,
+                }),
+            }
+        })
+        .collect();
+
+    let edges = analyser
+        .edges
+        .iter()
+        .map(|edge| VisualEdge {
+            id: edge.id,
+            from_node: edge.from_node,
+            from_out: edge.from_out,
+            to_node: edge.to_node,
+            fact: edge.fact.summary(MAX_ELEMENTS),
+            datatype: edge.fact.datatype.to_string(),
+            shape: edge.fact.shape.dims.iter().map(|d| format!("{}", d)).collect(),
+            open: edge.fact.shape.open,
+            streamed: edge.fact.shape.dims.iter().any(|d| d.is_streamed()),
+            constant: edge.fact.value.is_concrete(),
+            component: edge_component.get(&edge.id).cloned(),
+        })
+        .collect();
+
+    Ok(VisualGraph {
+        version: FORMAT_VERSION,
+        nodes,
+        edges,
+        groups: groups.into_iter().map(|(_, g)| g).collect(),
+        components,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serialize")]
+    use {
+        ndarray::arr1,
+        serde_json::{self, Value},
+        tfpb,
+        tfpb::types::DataType::DT_FLOAT,
+        Model, Tensor,
+    };
+
+    #[test]
+    fn scopes_of_names() {
+        assert!(scopes("relu").is_empty());
+        assert_eq!(scopes("a/b/c"), vec!["a", "a/b"]);
+    }
+
+    /// The expected document for `Relu(block/input * block/scale)`. If this
+    /// test fails after a change of the format, update it and increment
+    /// `FORMAT_VERSION` when the change can break a frontend.
+    #[cfg(feature = "serialize")]
+    const GOLDEN: &str = r#"{
+      "version": 1,
+      "nodes": [
+        {"id": 0, "name": "block/input", "op": "Placeholder", "group": "block", "component": null, "profile": null},
+        {"id": 1, "name": "block/scale", "op": "Const", "group": "block", "component": 0, "profile": null},
+        {"id": 2, "name": "block/mul", "op": "Mul", "group": "block", "component": null, "profile": null},
+        {"id": 3, "name": "relu", "op": "Relu", "group": null, "component": null, "profile": null}
+      ],
+      "edges": [
+        {"id": 0, "from_node": 0, "from_out": 0, "to_node": 2, "fact": "f32[S,2]", "datatype": "f32",
+         "shape": ["S", "2"], "open": false, "streamed": true, "constant": false, "component": null},
+        {"id": 1, "from_node": 1, "from_out": 0, "to_node": 2, "fact": "f32[2]=1.0,2.0", "datatype": "f32",
+         "shape": ["2"], "open": false, "streamed": false, "constant": true, "component": 0},
+        {"id": 2, "from_node": 2, "from_out": 0, "to_node": 3, "fact": "f32[S,2]", "datatype": "f32",
+         "shape": ["S", "2"], "open": false, "streamed": true, "constant": false, "component": null},
+        {"id": 3, "from_node": 3, "from_out": 0, "to_node": null, "fact": "f32[S,2]", "datatype": "f32",
+         "shape": ["S", "2"], "open": false, "streamed": true, "constant": false, "component": null}
+      ],
+      "groups": [
+        {"name": "block", "parent": null, "nodes": [0, 1, 2]}
+      ],
+      "components": [
+        {"id": 0, "nodes": [1], "edges": [1], "outputs": [1]}
+      ]
+    }"#;
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn golden_document() {
+        let graph = tfpb::graph()
+            .node(tfpb::node().name("block/input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(tfpb::node()
+                .name("block/scale".to_string())
+                .op("Const")
+                .attr("dtype", DT_FLOAT)
+                .attr("value", Tensor::F32(arr1(&[1., 2.]).into_dyn()).to_pb().unwrap()))
+            .node(tfpb::node()
+                .name("block/mul".to_string())
+                .op("Mul")
+                .attr("T", DT_FLOAT)
+                .input("block/input".to_string())
+                .input("block/scale".to_string()))
+            .node(tfpb::node().name("relu".to_string()).op("Relu").attr("T", DT_FLOAT).input("block/mul".to_string()));
+
+        let model = Model::new(graph).unwrap();
+        let input = model.nodes_by_name["block/input"];
+        let output = model.nodes_by_name["relu"];
+
+        let mut analyser = Analyser::new(model, output).unwrap();
+        analyser.hint(input, &"f32[S,2]".parse().unwrap()).unwrap();
+        analyser.run().unwrap();
+
+        let json = visualize(&analyser, None).unwrap().to_json().unwrap();
+        let document: Value = serde_json::from_str(&json).unwrap();
+        let golden: Value = serde_json::from_str(GOLDEN).unwrap();
+        assert_eq!(document, golden);
+    }
+}
@@ -0,0 +1,49 @@
+//! Escaping of the strings written in the text formats exported by the
+//! profiler, the analyser and the visualizer. JSON is written with serde.
+
+/// Escapes a field of a CSV file if needed.
+pub fn csv(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes a string into a DOT string literal.
+pub fn dot(string: &str) -> String {
+    let escaped = string.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Escapes a string for use in an XML document.
+pub fn xml(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_csv() {
+        assert_eq!(csv("conv"), "conv");
+        assert_eq!(csv("a,b"), "\"a,b\"");
+        assert_eq!(csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn escape_dot() {
+        assert_eq!(dot("conv\nConv2D"), "\"conv\\nConv2D\"");
+        assert_eq!(dot("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[test]
+    fn escape_xml() {
+        assert_eq!(xml("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
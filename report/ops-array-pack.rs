@@ -0,0 +1,96 @@
+use analyser::interface::*;
+use ops::prelude::*;
+use tfpb::types::DataType;
+
+impl<T: Datum> InferenceRulesOp for Pack<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The `n` inputs all have the same shape, and the output has the same
+    /// shape with an extra dimension of size `n` at position `axis`.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let output = &outputs[0];
+        let n = self.n as isize;
+        let axis = self.axis;
+
+        solver
+            .equals(&inputs.len, n)
+            .equals(&outputs.len, 1)
+            .equals(&output.datatype, T::datatype())
+            .equals(&output.shape[axis], n)
+            .for_each_input(inputs, move |solver, _, input| {
+                solver
+                    .equals(&input.datatype, T::datatype())
+                    .equals(&input.rank, &inputs[0].rank)
+                    .equals(&input.shape, &inputs[0].shape);
+            })
+            .given(&inputs[0].rank, move |solver, rank: usize| {
+                solver.equals(&output.rank, rank as isize + 1);
+
+                for i in 0..rank {
+                    let j = if i < axis { i } else { i + 1 };
+                    solver.equals(&output.shape[j], &inputs[0].shape[i]);
+                }
+            });
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for ConcatV2<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The first `n` inputs are concatenated along the axis given by the last
+    /// input, so the size of the output along that axis is the sum of their
+    /// sizes, and all the other dimensions are the same.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let output = &outputs[0];
+        let n = self.n;
+        let axis = &inputs[n];
+
+        solver
+            .equals(&inputs.len, n as isize + 1)
+            .equals(&outputs.len, 1)
+            .equals(&output.datatype, T::datatype())
+            .equals(&axis.datatype, DataType::DT_INT32)
+            .equals(&axis.rank, 0)
+            .for_each_input(inputs, move |solver, i, input| {
+                if i < n {
+                    solver
+                        .equals(&input.datatype, T::datatype())
+                        .equals(&input.rank, &output.rank);
+                }
+            })
+            .given(&axis.value, move |solver, axis: Tensor| {
+                let axis = match axis {
+                    Tensor::I32(ref array) if array.len() == 1 => *array.iter().next().unwrap(),
+                    _ => return,
+                };
+
+                solver.given(&output.rank, move |solver, rank: usize| {
+                    let axis = if axis < 0 { axis + rank as i32 } else { axis } as usize;
+
+                    let mut sizes: Vec<Box<Expression<Output = IntFact>>> =
+                        vec![Box::new((1, &output.shape[axis]).into_expr())];
+
+                    for i in 0..n {
+                        let input = &inputs[i];
+                        sizes.push(Box::new((-1, &input.shape[axis]).into_expr()));
+
+                        for j in (0..rank).filter(|&j| j != axis) {
+                            solver.equals(&input.shape[j], &output.shape[j]);
+                        }
+                    }
+
+                    solver.equals_zero(sizes);
+                });
+            });
+    }
+}
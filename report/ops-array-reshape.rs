@@ -0,0 +1,183 @@
+use analyser::interface::expressions::{product, product_of};
+use analyser::interface::*;
+use ops::prelude::*;
+use std::marker::PhantomData;
+use tfpb::types::DataType;
+use Result;
+
+/// Computes the target shape of a Reshape given the shape of its input.
+///
+/// The target can contain at most one -1, which stands for the dimension
+/// required to keep the same number of elements as the input.
+fn compute_shape(input: &[usize], target: &[i32]) -> Result<Vec<usize>> {
+    if target.iter().filter(|&&d| d == -1).count() > 1 {
+        bail!("The target shape {:?} contains several -1.", target);
+    }
+
+    let size: usize = input.iter().product();
+    let known: usize = target.iter().filter(|&&d| d != -1).map(|&d| d as usize).product();
+
+    if known == 0 || size % known != 0 {
+        bail!("Impossible to reshape {:?} into {:?}.", input, target);
+    }
+
+    Ok(target
+        .iter()
+        .map(|&d| if d == -1 { size / known } else { d as usize })
+        .collect())
+}
+
+/// The Reshape operation.
+#[derive(Debug, Clone, Default, new)]
+pub struct Reshape<T: Datum>(PhantomData<T>);
+
+impl<T: Datum> Op for Reshape<T> {
+    /// Evaluates the operation given the input tensors.
+    fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+        let (input, target) = args_2!(inputs);
+        let target = i32::tensor_to_view(&*target)?;
+
+        let input = T::tensor_into_array(input.into_tensor())?;
+        let shape = compute_shape(input.shape(), target.as_slice().unwrap())?;
+
+        Ok(vec![T::array_into_tensor(input.into_shape(shape)?).into()])
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for Reshape<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The known dimensions of the output come from the target shape, and the
+    /// one marked with -1 is deduced from the number of elements of the input,
+    /// which is the same as that of the output. This also works backwards, so
+    /// an unknown dimension of the input can be deduced from the output.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let input = &inputs[0];
+        let target = &inputs[1];
+        let output = &outputs[0];
+
+        solver
+            .equals(&inputs.len, 2)
+            .equals(&outputs.len, 1)
+            .equals(&input.datatype, T::datatype())
+            .equals(&target.datatype, DataType::DT_INT32)
+            .equals(&output.datatype, T::datatype())
+            .equals(&target.rank, 1)
+            .equals(&target.shape[0], &output.rank)
+            .equals(product_of(&input.shape), product_of(&output.shape))
+            .given(&target.value, move |solver, target: Tensor| {
+                let target = match i32::tensor_to_view(&target) {
+                    Ok(target) => target,
+                    Err(_) => return,
+                };
+
+                for (i, &d) in target.iter().enumerate() {
+                    if d >= 0 {
+                        solver.equals(&output.shape[i], d as isize);
+                    }
+                }
+            });
+    }
+}
+
+/// The Flatten operation, which collapses the dimensions before and after
+/// a given axis into a matrix.
+#[derive(Debug, Clone, new)]
+pub struct Flatten<T: Datum> {
+    axis: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Datum> Op for Flatten<T> {
+    /// Evaluates the operation given the input tensors.
+    fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+        let input = args_1!(inputs);
+        let input = T::tensor_into_array(input.into_tensor())?;
+
+        let rows = input.shape()[..self.axis].iter().product::<usize>();
+        let cols = input.shape()[self.axis..].iter().product::<usize>();
+
+        Ok(vec![T::array_into_tensor(input.into_shape(vec![rows, cols])?).into()])
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for Flatten<T> {
+    /// Registers the inference rules of the operator.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let input = &inputs[0];
+        let output = &outputs[0];
+        let axis = self.axis;
+
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&input.datatype, T::datatype())
+            .equals(&output.datatype, T::datatype())
+            .equals(&output.rank, 2)
+            .given(&input.rank, move |solver, rank: usize| {
+                let dim = |i: usize| -> Box<Expression<Output = IntFact>> {
+                    Box::new((&input.shape[i]).into_expr())
+                };
+
+                let rows = (0..axis).map(dim).collect();
+                let cols = (axis..rank).map(dim).collect();
+
+                solver
+                    .equals(&output.shape[0], product(rows))
+                    .equals(&output.shape[1], product(cols));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::prelude::*;
+    use ndarray::arr1;
+
+    /// Infers the shape of the output of a Reshape of a [2, 3, 4] tensor.
+    fn reshape(target: &[i32]) -> ShapeFact {
+        let input = TensorFact {
+            datatype: typefact!(DataType::DT_FLOAT),
+            shape: shapefact![2, 3, 4],
+            value: valuefact!(_),
+        };
+
+        let target = tensor_to_fact(Tensor::I32(arr1(target).into_dyn()));
+        let (_, outputs) = Reshape::<f32>::new().enrich(vec![input, target], vec![TensorFact::new()]).unwrap();
+
+        outputs[0].shape.clone()
+    }
+
+    #[test]
+    fn reshape_infers_last_dimension() {
+        assert_eq!(reshape(&[6, -1]), shapefact![6, 4]);
+    }
+
+    #[test]
+    fn reshape_infers_first_dimension() {
+        assert_eq!(reshape(&[-1, 6]), shapefact![4, 6]);
+    }
+
+    #[test]
+    fn flatten_infers_both_dimensions() {
+        let input = TensorFact {
+            datatype: typefact!(DataType::DT_FLOAT),
+            shape: shapefact![2, 3, 4],
+            value: valuefact!(_),
+        };
+
+        let (_, outputs) = Flatten::<f32>::new(1).enrich(vec![input], vec![TensorFact::new()]).unwrap();
+        assert_eq!(outputs[0].shape, shapefact![2, 12]);
+    }
+}
@@ -0,0 +1,283 @@
+use analyser::interface::*;
+use analyser::prelude::*;
+use ndarray::prelude::*;
+use ops::prelude::*;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use tfpb::types::DataType;
+use Result;
+
+/// Converts a list of dimensions into a rank 1 tensor of type T.
+fn dims_to_tensor<T: Datum>(dims: &[usize]) -> Result<Tensor> {
+    let dims: Vec<T> = dims
+        .iter()
+        .map(|&d| T::from_usize(d))
+        .collect::<Result<_>>()?;
+
+    Ok(T::array_into_tensor(Array1::from_vec(dims).into_dyn()))
+}
+
+/// Converts a single value into a rank 0 tensor of type T.
+fn scalar_to_tensor<T: Datum>(value: usize) -> Result<Tensor> {
+    Ok(T::array_into_tensor(arr0(T::from_usize(value)?).into_dyn()))
+}
+
+/// The Shape operation, which returns the shape of its input.
+#[derive(Debug, Clone, Default, new)]
+pub struct Shape<T: Datum> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Datum> Op for Shape<T> {
+    /// Evaluates the operation given the input tensors.
+    fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+        let input = args_1!(inputs);
+        Ok(vec![dims_to_tensor::<T>(input.shape())?.into()])
+    }
+
+    /// Returns the attributes of the operation and their values.
+    fn get_attributes(&self) -> HashMap<&'static str, Attr> {
+        hashmap!{ "out_type" => Attr::DataType(T::datatype()) }
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for Shape<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The value of the output only depends on the shape of the input, so we
+    /// can compute it as soon as the shape is known, even if the value of the
+    /// input isn't. This lets the constant folding get rid of entire chains
+    /// of shape computations, e.g. Shape -> StridedSlice -> Pack -> Reshape.
+    /// The `given` closure only fires once the shape is fully known, because
+    /// a ShapeFact can only be converted into a Vec<usize> when it's concrete.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&outputs[0].datatype, T::datatype())
+            .equals(&outputs[0].rank, 1)
+            .equals(&outputs[0].shape[0], &inputs[0].rank)
+            .given(&inputs[0].shape, move |solver, shape: Vec<usize>| {
+                if let Ok(tensor) = dims_to_tensor::<T>(&shape) {
+                    solver.equals(&outputs[0].value, valuefact!(tensor));
+                }
+            });
+    }
+}
+
+/// The Rank operation, which returns the rank of its input.
+#[derive(Debug, Clone, Default, new)]
+pub struct Rank;
+
+impl Op for Rank {
+    /// Evaluates the operation given the input tensors.
+    fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+        let input = args_1!(inputs);
+        Ok(vec![scalar_to_tensor::<i32>(input.shape().len())?.into()])
+    }
+}
+
+impl InferenceRulesOp for Rank {
+    /// Registers the inference rules of the operator.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&outputs[0].datatype, DataType::DT_INT32)
+            .equals(&outputs[0].rank, 0)
+            .given(&inputs[0].rank, move |solver, rank: usize| {
+                if let Ok(tensor) = scalar_to_tensor::<i32>(rank) {
+                    solver.equals(&outputs[0].value, valuefact!(tensor));
+                }
+            });
+    }
+}
+
+/// The Size operation, which returns the number of elements of its input.
+#[derive(Debug, Clone, Default, new)]
+pub struct Size<T: Datum> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Datum> Op for Size<T> {
+    /// Evaluates the operation given the input tensors.
+    fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+        let input = args_1!(inputs);
+        let size = input.shape().iter().product();
+        Ok(vec![scalar_to_tensor::<T>(size)?.into()])
+    }
+
+    /// Returns the attributes of the operation and their values.
+    fn get_attributes(&self) -> HashMap<&'static str, Attr> {
+        hashmap!{ "out_type" => Attr::DataType(T::datatype()) }
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for Size<T> {
+    /// Registers the inference rules of the operator.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&outputs[0].datatype, T::datatype())
+            .equals(&outputs[0].rank, 0)
+            .given(&inputs[0].shape, move |solver, shape: Vec<usize>| {
+                let size = shape.iter().product();
+                if let Ok(tensor) = scalar_to_tensor::<T>(size) {
+                    solver.equals(&outputs[0].value, valuefact!(tensor));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::Analyser;
+    use ops::OpBuilder;
+    use tfpb;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::{DT_FLOAT, DT_INT32};
+    use Model;
+
+    fn f32s(shape: ShapeFact) -> TensorFact {
+        TensorFact {
+            datatype: typefact!(DT_FLOAT),
+            shape,
+            value: valuefact!(_),
+        }
+    }
+
+    /// Infers the facts about the output of a node given its only input.
+    fn enrich(node: NodeDef, input: TensorFact) -> TensorFact {
+        let op = OpBuilder::new().build(&node).unwrap();
+        let (_, mut outputs) = op.enrich(vec![input], vec![TensorFact::new()]).unwrap();
+        outputs.remove(0)
+    }
+
+    fn node(op: &str) -> NodeDef {
+        tfpb::node()
+            .name(op.to_lowercase())
+            .op(op)
+            .attr("T", DT_FLOAT)
+            .attr("out_type", DT_INT32)
+    }
+
+    fn i32s(values: &[i32]) -> Tensor {
+        Tensor::I32(arr1(values).into_dyn())
+    }
+
+    fn i32_scalar(value: i32) -> Tensor {
+        Tensor::I32(arr0(value).into_dyn())
+    }
+
+    #[test]
+    fn shape_of_concrete_shape() {
+        let output = enrich(node("Shape"), f32s(shapefact![2, 3, 4]));
+        assert_eq!(output.shape, shapefact![3]);
+        assert_eq!(output.value.concretize(), Some(i32s(&[2, 3, 4])));
+    }
+
+    #[test]
+    fn shape_of_streamed_shape() {
+        let output = enrich(node("Shape"), f32s(shapefact![S, 40]));
+        assert_eq!(output.shape, shapefact![2]);
+        assert_eq!(output.value, valuefact!(_));
+    }
+
+    #[test]
+    fn rank_of_concrete_and_streamed_shapes() {
+        let output = enrich(node("Rank"), f32s(shapefact![2, 3, 4]));
+        assert_eq!(output.value.concretize(), Some(i32_scalar(3)));
+
+        let output = enrich(node("Rank"), f32s(shapefact![S, 40]));
+        assert_eq!(output.value.concretize(), Some(i32_scalar(2)));
+    }
+
+    #[test]
+    fn size_of_concrete_and_streamed_shapes() {
+        let output = enrich(node("Size"), f32s(shapefact![2, 3, 4]));
+        assert_eq!(output.value.concretize(), Some(i32_scalar(24)));
+
+        let output = enrich(node("Size"), f32s(shapefact![S, 40]));
+        assert_eq!(output.shape, ShapeFact::closed(vec![]));
+        assert_eq!(output.value, valuefact!(_));
+    }
+
+    /// Builds `Reshape(input, Pack(StridedSlice(Shape(input), 0, 1), -1))`,
+    /// which flattens all the dimensions of the input but the first one.
+    fn flatten_graph() -> tfpb::graph::GraphDef {
+        let constant = |name: &str, tensor: Tensor| {
+            tfpb::node()
+                .name(name.to_string())
+                .op("Const")
+                .attr("dtype", DT_INT32)
+                .attr("value", tensor.to_pb().unwrap())
+        };
+
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(node("Shape").input("input".to_string()))
+            .node(constant("begin", i32s(&[0])))
+            .node(constant("end", i32s(&[1])))
+            .node(constant("strides", i32s(&[1])))
+            .node(tfpb::node()
+                .name("slice".to_string())
+                .op("StridedSlice")
+                .attr("T", DT_INT32)
+                .attr("Index", DT_INT32)
+                .attr("shrink_axis_mask", 1)
+                .input("shape".to_string())
+                .input("begin".to_string())
+                .input("end".to_string())
+                .input("strides".to_string()))
+            .node(constant("minus_one", i32_scalar(-1)))
+            .node(tfpb::node()
+                .name("pack".to_string())
+                .op("Pack")
+                .attr("T", DT_INT32)
+                .attr("N", 2)
+                .attr("axis", 0)
+                .input("slice".to_string())
+                .input("minus_one".to_string()))
+            .node(tfpb::node()
+                .name("reshape".to_string())
+                .op("Reshape")
+                .attr("T", DT_FLOAT)
+                .attr("Tshape", DT_INT32)
+                .input("input".to_string())
+                .input("pack".to_string()))
+    }
+
+    #[test]
+    fn fold_shape_chain_into_reshape_target() {
+        let model = Model::new(flatten_graph()).unwrap();
+        let input = model.nodes_by_name["input"];
+        let reshape = model.nodes_by_name["reshape"];
+
+        let mut analyser = Analyser::new(model, reshape).unwrap();
+        analyser.hint(input, &f32s(shapefact![2, 3, 4])).unwrap();
+        analyser.run().unwrap();
+
+        let target = &analyser.edges[analyser.prev_edges[reshape][1]].fact;
+        assert_eq!(target.value.concretize(), Some(i32s(&[2, -1])));
+
+        let output = &analyser.edges[analyser.next_edges[reshape][0]].fact;
+        assert_eq!(output.shape, shapefact![2, 12]);
+    }
+}
@@ -0,0 +1,210 @@
+use analyser::interface::expressions::tensor_of;
+use analyser::interface::*;
+use ops::prelude::*;
+use tfpb::types::DataType;
+
+/// Returns the value of a scalar int32 tensor.
+fn scalar(tensor: &Tensor) -> Option<isize> {
+    match tensor {
+        Tensor::I32(array) if array.len() == 1 => array.iter().next().map(|&v| v as isize),
+        _ => None,
+    }
+}
+
+/// Wraps a known integer into an expression.
+fn constant(value: isize) -> Box<Expression<Output = IntFact>> {
+    Box::new(value.into_expr())
+}
+
+impl<T: Datum> InferenceRulesOp for Gather<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// When the indices are known and the params are a vector of int32, the
+    /// elements of the output are the elements of the params at the given
+    /// indices. The elements of a value are only known once the whole value
+    /// is, so e.g. gathering from the Shape of a streamed tensor gives no
+    /// value. Negative indices are invalid, so nothing is inferred from them.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let params = &inputs[0];
+        let indices = &inputs[1];
+        let output = &outputs[0];
+
+        solver
+            .equals(&inputs.len, 2)
+            .equals(&outputs.len, 1)
+            .equals(&params.datatype, T::datatype())
+            .equals(&indices.datatype, DataType::DT_INT32)
+            .equals(&output.datatype, T::datatype())
+            .equals_zero(wrap!(
+                (1, &output.rank),
+                (-1, &indices.rank),
+                (-1, &params.rank),
+                1
+            ))
+            .given(&indices.value, move |solver, indices: Tensor| {
+                let indices = match indices {
+                    Tensor::I32(ref array) => array.clone(),
+                    _ => return,
+                };
+
+                solver.given(&params.rank, move |solver, rank: usize| {
+                    if rank != 1 || T::datatype() != DataType::DT_INT32 {
+                        return;
+                    }
+
+                    if indices.iter().any(|&k| k < 0) {
+                        return;
+                    }
+
+                    let elements = indices
+                        .iter()
+                        .map(|&k| Box::new((&params.value[k as usize]).into_expr()) as Box<_>)
+                        .collect();
+
+                    if let Ok(value) = tensor_of(DataType::DT_INT32, indices.shape().to_vec(), elements) {
+                        solver.equals(&output.value, value);
+                    }
+                });
+            });
+    }
+}
+
+impl InferenceRulesOp for Range {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The number of elements of the output is `ceil((limit - start) / delta)`,
+    /// and its value is known as soon as the three scalar inputs are.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let output = &outputs[0];
+
+        solver
+            .equals(&inputs.len, 3)
+            .equals(&outputs.len, 1)
+            .equals(&inputs[0].datatype, DataType::DT_INT32)
+            .equals(&inputs[1].datatype, DataType::DT_INT32)
+            .equals(&inputs[2].datatype, DataType::DT_INT32)
+            .equals(&output.datatype, DataType::DT_INT32)
+            .equals(&inputs[0].rank, 0)
+            .equals(&inputs[1].rank, 0)
+            .equals(&inputs[2].rank, 0)
+            .equals(&output.rank, 1)
+            .given(&inputs[0].value, move |solver, start: Tensor| {
+                solver.given(&inputs[1].value, move |solver, limit: Tensor| {
+                    let start = start.clone();
+                    solver.given(&inputs[2].value, move |solver, delta: Tensor| {
+                        let (start, limit, delta) = match (scalar(&start), scalar(&limit), scalar(&delta)) {
+                            (Some(s), Some(l), Some(d)) if d != 0 => (s, l, d),
+                            _ => return,
+                        };
+
+                        let len = ((limit - start + delta - delta.signum()) / delta).max(0);
+                        let elements = (0..len).map(|i| constant(start + i * delta)).collect();
+
+                        solver.equals(&output.shape[0], len);
+                        if let Ok(value) = tensor_of(DataType::DT_INT32, vec![len as usize], elements) {
+                            solver.equals(&output.value, value);
+                        }
+                    });
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::prelude::*;
+    use ndarray::{arr0, arr1};
+    use ops::OpBuilder;
+    use tfpb;
+    use tfpb::types::DataType::DT_INT32;
+
+    fn i32s(values: &[i32]) -> TensorFact {
+        tensor_to_fact(Tensor::I32(arr1(values).into_dyn()))
+    }
+
+    fn i32_scalar(value: i32) -> TensorFact {
+        tensor_to_fact(Tensor::I32(arr0(value).into_dyn()))
+    }
+
+    /// Infers the facts about the output of a Gather of int32 params.
+    fn gather(params: TensorFact, indices: &[i32]) -> TensorFact {
+        let node = tfpb::node()
+            .name("gather".to_string())
+            .op("Gather")
+            .attr("Tparams", DT_INT32)
+            .attr("Tindices", DT_INT32);
+
+        let op = OpBuilder::new().build(&node).unwrap();
+        let (_, mut outputs) = op.enrich(vec![params, i32s(indices)], vec![TensorFact::new()]).unwrap();
+        outputs.remove(0)
+    }
+
+    #[test]
+    fn gather_known_params() {
+        let output = gather(i32s(&[10, 20, 30, 40, 50]), &[4, 0, 2]);
+        assert_eq!(output.value.concretize(), Some(Tensor::I32(arr1(&[50, 10, 30]).into_dyn())));
+    }
+
+    #[test]
+    fn gather_large_indices() {
+        let params: Vec<i32> = (0..20).map(|i| i * 10).collect();
+        let output = gather(i32s(&params), &[15, 9]);
+        assert_eq!(output.value.concretize(), Some(Tensor::I32(arr1(&[150, 90]).into_dyn())));
+    }
+
+    #[test]
+    fn gather_negative_indices() {
+        let params = TensorFact {
+            datatype: typefact!(DT_INT32),
+            shape: shapefact![_],
+            value: valuefact!(_),
+        };
+
+        assert_eq!(gather(params, &[-1]).value, valuefact!(_));
+    }
+
+    #[test]
+    fn gather_partially_known_params() {
+        let params = TensorFact {
+            datatype: typefact!(DT_INT32),
+            shape: shapefact![2],
+            value: valuefact!(_),
+        };
+
+        assert_eq!(gather(params, &[1]).value, valuefact!(_));
+    }
+
+    #[test]
+    fn range() {
+        let node = tfpb::node().name("range".to_string()).op("Range").attr("Tidx", DT_INT32);
+        let op = OpBuilder::new().build(&node).unwrap();
+
+        let inputs = vec![i32_scalar(2), i32_scalar(11), i32_scalar(3)];
+        let (_, outputs) = op.enrich(inputs, vec![TensorFact::new()]).unwrap();
+
+        assert_eq!(outputs[0].shape, shapefact![3]);
+        assert_eq!(outputs[0].value.concretize(), Some(Tensor::I32(arr1(&[2, 5, 8]).into_dyn())));
+    }
+
+    #[test]
+    fn range_empty() {
+        let node = tfpb::node().name("range".to_string()).op("Range").attr("Tidx", DT_INT32);
+        let op = OpBuilder::new().build(&node).unwrap();
+
+        let inputs = vec![i32_scalar(5), i32_scalar(2), i32_scalar(1)];
+        let (_, outputs) = op.enrich(inputs, vec![TensorFact::new()]).unwrap();
+
+        assert_eq!(outputs[0].shape, shapefact![0]);
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use tfpb::node_def::NodeDef;
+use Result;
+
+/// A function which builds an operation from its protobuf definition.
+pub type OpRegister = HashMap<&'static str, fn(&NodeDef) -> Result<Box<Op>>>;
+
+/// Builds operations from their protobuf definitions.
+pub struct OpBuilder(OpRegister);
+
+impl OpBuilder {
+    /// Creates a builder which knows about all the supported operations.
+    pub fn new() -> OpBuilder {
+        let mut reg = OpRegister::new();
+        array::register_all_ops(&mut reg);
+        cast::register_all_ops(&mut reg);
+        konst::register_all_ops(&mut reg);
+        math::register_all_ops(&mut reg);
+        nn::register_all_ops(&mut reg);
+        OpBuilder(reg)
+    }
+
+    /// Returns the names of the supported operations, in alphabetical order.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.0.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Builds an operation from its protobuf definition.
+    ///
+    /// Unsupported operations are built as an `UnimplementedOp`, which fails
+    /// when evaluated.
+    pub fn build(&self, pb: &NodeDef) -> Result<Box<Op>> {
+        match self.0.get(pb.get_op()) {
+            Some(builder) => builder(pb),
+            None => Ok(Box::new(UnimplementedOp(pb.get_op().to_string(), pb.to_owned()))),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use analyser::interface::*;
+use analyser::prelude::*;
+use ndarray::prelude::*;
+use ndarray::Zip;
+use ops::prelude::*;
+use std::marker::PhantomData;
+use Result;
+
+/// Computes the shape obtained by broadcasting two shapes together.
+fn broadcast_shapes(a: &[usize], b: &[usize]) -> Result<Vec<usize>> {
+    let rank = a.len().max(b.len());
+    let dim = |s: &[usize], k: usize| if k < s.len() { s[s.len() - 1 - k] } else { 1 };
+
+    let mut shape = (0..rank)
+        .map(|k| match (dim(a, k), dim(b, k)) {
+            (1, d) | (d, 1) => Ok(d),
+            (x, y) if x == y => Ok(x),
+            (x, y) => bail!("Impossible to broadcast {:?} with {:?} ({} != {}).", a, b, x, y),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    shape.reverse();
+    Ok(shape)
+}
+
+/// Defines an element-wise binary operation which broadcasts its inputs
+/// following the semantics of NumPy, along with its inference rules.
+macro_rules! element_bin {
+    ($name:ident, $op:expr) => {
+        #[derive(Debug, Clone, Default, new)]
+        pub struct $name<T: Datum>(PhantomData<T>);
+
+        impl<T: Datum> Op for $name<T> {
+            /// Evaluates the operation given the input tensors.
+            fn eval(&self, mut inputs: Vec<TensorView>) -> Result<Vec<TensorView>> {
+                let (a, b) = args_2!(inputs);
+                let a = T::tensor_to_view(&*a)?;
+                let b = T::tensor_to_view(&*b)?;
+
+                let shape = broadcast_shapes(a.shape(), b.shape())?;
+                let a = a.broadcast(&*shape).ok_or("Can't broadcast the first input.")?;
+                let b = b.broadcast(&*shape).ok_or("Can't broadcast the second input.")?;
+
+                let mut result = ArrayD::<T>::default(shape);
+                Zip::from(&mut result)
+                    .and(&a)
+                    .and(&b)
+                    .apply(|r, &x, &y| *r = $op(x, y));
+
+                Ok(vec![T::array_into_tensor(result).into()])
+            }
+        }
+
+        impl<T: Datum> InferenceRulesOp for $name<T> {
+            /// Registers the inference rules of the operator.
+            fn rules<'r, 'p: 'r, 's: 'r>(
+                &'s self,
+                solver: &mut Solver<'r>,
+                inputs: &'p TensorsProxy,
+                outputs: &'p TensorsProxy,
+            ) {
+                solver
+                    .equals(&inputs.len, 2)
+                    .equals(&outputs.len, 1)
+                    .equals(&inputs[0].datatype, T::datatype())
+                    .equals(&inputs[1].datatype, T::datatype())
+                    .equals(&outputs[0].datatype, T::datatype())
+                    .broadcasts(&outputs[0].shape, &[&inputs[0].shape, &inputs[1].shape]);
+            }
+        }
+    };
+}
+
+element_bin!(Add, |a: T, b: T| a + b);
+element_bin!(Sub, |a: T, b: T| a - b);
+element_bin!(Mul, |a: T, b: T| a * b);
+element_bin!(Div, |a: T, b: T| a / b);
+element_bin!(Maximum, |a: T, b: T| if a > b { a } else { b });
+element_bin!(Minimum, |a: T, b: T| if a < b { a } else { b });
+element_bin!(SquaredDifference, |a: T, b: T| (a - b) * (a - b));
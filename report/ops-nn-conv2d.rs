@@ -0,0 +1,220 @@
+use analyser::interface::expressions::{div, sum};
+use analyser::interface::*;
+use ops::nn::local_patch::{DataFormat, Padding};
+use ops::nn::pools::{Pool, Pooler};
+use ops::prelude::*;
+use ops::Attr;
+
+/// Returns why the attributes of a convolution or pooling operation aren't
+/// supported by its rules, if they aren't.
+///
+/// The rules assume the NHWC format, which is the default, and no dilation.
+fn unsupported(op: &Op) -> Option<&'static str> {
+    let attributes = op.get_attributes();
+
+    match attributes.get("data_format") {
+        None | Some(Attr::DataFormat(DataFormat::NHWC)) => (),
+        _ => return Some("Only the NHWC data format is supported."),
+    }
+
+    match attributes.get("dilations") {
+        Some(Attr::UsizeVec(d)) if d.iter().any(|&d| d != 1) => Some("Dilated operations aren't supported."),
+        Some(Attr::IsizeVec(d)) if d.iter().any(|&d| d != 1) => Some("Dilated operations aren't supported."),
+        _ => None,
+    }
+}
+
+impl<T: Datum> InferenceRulesOp for Conv2D<T> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// The data is in NHWC format, and the filter is in HWIO format, so the
+    /// other formats and dilated convolutions make the inference fail. Along
+    /// each spatial dimension, the size of the output is `(i - k) / s + 1`
+    /// with VALID padding and `(i + s - 1) / s` with SAME padding, where `i`
+    /// is the size of the input, `k` the size of the filter and `s` the
+    /// stride.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let data = &inputs[0];
+        let filter = &inputs[1];
+        let output = &outputs[0];
+
+        if let Some(message) = unsupported(self) {
+            solver.unsupported(message);
+            return;
+        }
+
+        solver
+            .equals(&inputs.len, 2)
+            .equals(&outputs.len, 1)
+            .equals(&data.datatype, T::datatype())
+            .equals(&filter.datatype, T::datatype())
+            .equals(&output.datatype, T::datatype())
+            .equals(&data.rank, 4)
+            .equals(&filter.rank, 4)
+            .equals(&output.rank, 4)
+            .equals(&output.shape[0], &data.shape[0])
+            .equals(&filter.shape[2], &data.shape[3])
+            .equals(&output.shape[3], &filter.shape[3]);
+
+        let strides = [self.0.v_stride as isize, self.0.h_stride as isize];
+
+        for (i, &stride) in strides.iter().enumerate() {
+            let input = &data.shape[i + 1];
+            let kernel = &filter.shape[i];
+
+            match self.0.padding {
+                Padding::Valid => {
+                    solver
+                        .less_or_equal(kernel, input, "input shorter than kernel")
+                        .equals(
+                            &output.shape[i + 1],
+                            sum(div(sum(input, (-1, kernel)), stride), 1),
+                        );
+                }
+
+                Padding::Same => {
+                    solver.equals(&output.shape[i + 1], div(sum(input, stride - 1), stride));
+                }
+            }
+        }
+    }
+}
+
+impl<T: Datum, P: Pooler<T>> InferenceRulesOp for Pool<T, P> {
+    /// Registers the inference rules of the operator.
+    ///
+    /// MaxPool and AvgPool follow the same rules as Conv2D, except that the
+    /// kernel size is an attribute, and that the output has the same number
+    /// of channels as the input.
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        solver: &mut Solver<'r>,
+        inputs: &'p TensorsProxy,
+        outputs: &'p TensorsProxy,
+    ) {
+        let data = &inputs[0];
+        let output = &outputs[0];
+
+        if let Some(message) = unsupported(self) {
+            solver.unsupported(message);
+            return;
+        }
+
+        solver
+            .equals(&inputs.len, 1)
+            .equals(&outputs.len, 1)
+            .equals(&data.datatype, T::datatype())
+            .equals(&output.datatype, T::datatype())
+            .equals(&data.rank, 4)
+            .equals(&output.rank, 4)
+            .equals(&output.shape[0], &data.shape[0])
+            .equals(&output.shape[3], &data.shape[3]);
+
+        let strides = [self.0.v_stride as isize, self.0.h_stride as isize];
+        let kernel = [(self.1).0 as isize, (self.1).1 as isize];
+
+        for i in 0..2 {
+            let input = &data.shape[i + 1];
+            let (kernel, stride) = (kernel[i], strides[i]);
+
+            match self.0.padding {
+                Padding::Valid => {
+                    solver
+                        .less_or_equal(kernel, input, "input shorter than kernel")
+                        .equals(&output.shape[i + 1], sum(div(sum(input, 1 - kernel), stride), 1));
+                }
+
+                Padding::Same => {
+                    solver.equals(&output.shape[i + 1], div(sum(input, stride - 1), stride));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analyser::prelude::*;
+    use ops::OpBuilder;
+    use tfpb;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::DT_FLOAT;
+    use Result;
+
+    fn f32s(shape: ShapeFact) -> TensorFact {
+        TensorFact {
+            datatype: typefact!(DT_FLOAT),
+            shape,
+            value: valuefact!(_),
+        }
+    }
+
+    fn conv(padding: &str) -> NodeDef {
+        tfpb::node()
+            .name("conv".to_string())
+            .op("Conv2D")
+            .attr("T", DT_FLOAT)
+            .attr("padding", padding.to_string())
+            .attr("strides", vec![1, 2, 2, 1])
+    }
+
+    fn pool(op: &str, padding: &str) -> NodeDef {
+        tfpb::node()
+            .name("pool".to_string())
+            .op(op)
+            .attr("T", DT_FLOAT)
+            .attr("padding", padding.to_string())
+            .attr("ksize", vec![1, 3, 2, 1])
+            .attr("strides", vec![1, 2, 2, 1])
+    }
+
+    /// Infers the facts about the output of a node given its inputs.
+    fn enrich(node: &NodeDef, inputs: Vec<TensorFact>) -> Result<TensorFact> {
+        let op = OpBuilder::new().build(node)?;
+        let (_, mut outputs) = op.enrich(inputs, vec![TensorFact::new()])?;
+        Ok(outputs.remove(0))
+    }
+
+    #[test]
+    fn conv2d_shapes() {
+        let inputs = vec![f32s(shapefact![1, 7, 8, 2]), f32s(shapefact![3, 3, 2, 4])];
+        assert_eq!(enrich(&conv("VALID"), inputs.clone()).unwrap().shape, shapefact![1, 3, 3, 4]);
+        assert_eq!(enrich(&conv("SAME"), inputs).unwrap().shape, shapefact![1, 4, 4, 4]);
+    }
+
+    #[test]
+    fn conv2d_rejects_nchw() {
+        let node = conv("VALID").attr("data_format", "NCHW".to_string());
+        let inputs = vec![f32s(shapefact![1, 2, 7, 8]), f32s(shapefact![3, 3, 2, 4])];
+        assert!(enrich(&node, inputs).is_err());
+    }
+
+    #[test]
+    fn conv2d_rejects_dilations() {
+        let node = conv("VALID").attr("dilations", vec![1, 2, 2, 1]);
+        let inputs = vec![f32s(shapefact![1, 7, 8, 2]), f32s(shapefact![3, 3, 2, 4])];
+        assert!(enrich(&node, inputs).is_err());
+    }
+
+    #[test]
+    fn pool_shapes() {
+        for op in &["MaxPool", "AvgPool"] {
+            let input = vec![f32s(shapefact![1, 7, 8, 2])];
+            assert_eq!(enrich(&pool(op, "VALID"), input.clone()).unwrap().shape, shapefact![1, 3, 4, 2]);
+            assert_eq!(enrich(&pool(op, "SAME"), input).unwrap().shape, shapefact![1, 4, 4, 2]);
+        }
+    }
+
+    #[test]
+    fn pool_streamed_shapes() {
+        let input = vec![f32s(shapefact![1, S, 8, 2])];
+        let output = enrich(&pool("MaxPool", "VALID"), input).unwrap();
+        assert_eq!(output.shape, shapefact![1, S, 4, 2]);
+    }
+}
@@ -0,0 +1,401 @@
+use super::eval_with;
+use ndarray::ArrayD;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tfpb::types::DataType;
+use zip::ZipArchive;
+use {Model, Plan, Result, Tensor};
+
+/// Parses a tensor in the .npy format.
+///
+/// Only little-endian arrays of f32, f64, i32, i8 and u8 in C order are
+/// supported, which covers the tensors of the models we deploy.
+pub fn parse_npy(bytes: &[u8]) -> Result<Tensor> {
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        bail!("The file isn't in the .npy format.");
+    }
+
+    let (length, start) = match bytes[6] {
+        1 => (bytes[8] as usize | (bytes[9] as usize) << 8, 10),
+        2 | 3 if bytes.len() >= 12 => (
+            bytes[8] as usize | (bytes[9] as usize) << 8 | (bytes[10] as usize) << 16 | (bytes[11] as usize) << 24,
+            12,
+        ),
+        v => bail!("Unsupported version {} of the .npy format.", v),
+    };
+
+    if bytes.len() < start + length {
+        bail!("The header of the .npy file is truncated.");
+    }
+
+    let header = String::from_utf8_lossy(&bytes[start..start + length]);
+    let data = &bytes[start + length..];
+
+    // The header is a Python dictionary literal, e.g.
+    // {'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }
+    let field = |name: &str| -> Result<String> {
+        let key = format!("'{}':", name);
+        match header.find(&key[..]) {
+            Some(i) => Ok(header[i + key.len()..].trim_left().to_string()),
+            None => bail!("Missing field {} in the .npy header.", name),
+        }
+    };
+
+    let descr = field("descr")?;
+    let descr = descr.split('\'').nth(1).ok_or("Invalid descr in the .npy header.")?.to_string();
+
+    if field("fortran_order")?.starts_with("True") {
+        bail!("Arrays in Fortran order are not supported.");
+    }
+
+    let shape = field("shape")?;
+    let shape = &shape[1..shape.find(')').ok_or("Invalid shape in the .npy header.")?];
+    let shape = shape
+        .split(',')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| Ok(d.parse::<usize>().map_err(|_| format!("Invalid dimension {}.", d))?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let len: usize = shape.iter().product();
+
+    macro_rules! read {
+        ($variant:ident, $size:expr, $convert:expr) => {{
+            if data.len() < len * $size {
+                bail!("The data of the .npy file is truncated.");
+            }
+
+            let values = data.chunks($size).take(len).map($convert).collect();
+            Tensor::$variant(ArrayD::from_shape_vec(shape, values)?)
+        }};
+    }
+
+    let word = |c: &[u8]| c.iter().rev().fold(0u64, |acc, &b| acc << 8 | b as u64);
+
+    let tensor = match &descr[..] {
+        "<f4" => read!(F32, 4, |c| f32::from_bits(word(c) as u32)),
+        "<f8" => read!(F64, 8, |c| f64::from_bits(word(c))),
+        "<i4" => read!(I32, 4, |c| word(c) as u32 as i32),
+        "|i1" => read!(I8, 1, |c| c[0] as i8),
+        "|u1" => read!(U8, 1, |c| c[0]),
+        _ => bail!("Unsupported datatype {} in the .npy file.", descr),
+    };
+
+    Ok(tensor)
+}
+
+/// Loads the tensors of an .npz archive, indexed by their names.
+///
+/// The value of the first output of a node is named after the node, and the
+/// value of its k-th output is named `name:k`.
+pub fn load_npz<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Tensor>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut tensors = HashMap::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().trim_right_matches(".npy").to_string();
+
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+
+        let tensor = parse_npy(&bytes).map_err(|e| format!("While reading {}: {}", name, e))?;
+        tensors.insert(name, tensor);
+    }
+
+    Ok(tensors)
+}
+
+/// The tolerance of the comparison of two values.
+///
+/// As with `numpy.allclose`, two values `a` and `b` are close enough when
+/// `|a - b| <= absolute + relative * |b|`, or when they are equal (which
+/// covers infinities of the same sign). A NaN is never close to anything,
+/// unless `equal_nan` is set, in which case two NaNs are close.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+    pub equal_nan: bool,
+}
+
+impl Tolerance {
+    /// Returns whether a value is close enough to its reference.
+    pub fn accepts(&self, found: f64, expected: f64) -> bool {
+        if found.is_nan() || expected.is_nan() {
+            return self.equal_nan && found.is_nan() && expected.is_nan();
+        }
+
+        found == expected || (found - expected).abs() <= self.absolute + self.relative * expected.abs()
+    }
+}
+
+/// The tolerances to use for each datatype.
+#[derive(Debug, Clone)]
+pub struct Tolerances(pub HashMap<DataType, Tolerance>);
+
+impl Default for Tolerances {
+    fn default() -> Tolerances {
+        let mut tolerances = HashMap::new();
+        tolerances.insert(DataType::DT_FLOAT, Tolerance { absolute: 1e-5, relative: 1e-4, equal_nan: false });
+        tolerances.insert(DataType::DT_DOUBLE, Tolerance { absolute: 1e-8, relative: 1e-7, equal_nan: false });
+        Tolerances(tolerances)
+    }
+}
+
+impl Tolerances {
+    /// Returns the tolerance for a given datatype, which is exact by default.
+    pub fn get(&self, datatype: DataType) -> Tolerance {
+        self.0
+            .get(&datatype)
+            .cloned()
+            .unwrap_or(Tolerance { absolute: 0., relative: 0., equal_nan: false })
+    }
+}
+
+/// The comparison of the value of an output of a node with its reference.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct NodeComparison {
+    pub name: String,
+    pub op_name: String,
+
+    // The maximum absolute and relative errors between the elements.
+    pub max_absolute: f64,
+    pub max_relative: f64,
+
+    // Whether the value is close enough to the reference, and why not.
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// The comparison of the evaluation of a model with reference values.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    // The comparison of every output which has a reference value, in the
+    // order of evaluation.
+    pub nodes: Vec<NodeComparison>,
+
+    // The name of the first output which isn't close enough to its reference.
+    pub first_divergence: Option<String>,
+}
+
+/// Returns the elements of a numeric tensor as f64.
+fn to_f64(tensor: &Tensor) -> Result<Vec<f64>> {
+    let values = match tensor {
+        Tensor::F32(a) => a.iter().map(|&v| v as f64).collect(),
+        Tensor::F64(a) => a.iter().cloned().collect(),
+        Tensor::I32(a) => a.iter().map(|&v| v as f64).collect(),
+        Tensor::I8(a) => a.iter().map(|&v| v as f64).collect(),
+        Tensor::U8(a) => a.iter().map(|&v| v as f64).collect(),
+        _ => bail!("Can't compare tensors of type {:?}.", tensor.datatype()),
+    };
+
+    Ok(values)
+}
+
+/// Compares a tensor with its reference value.
+fn compare_tensors(
+    name: &str,
+    op_name: &str,
+    found: &Tensor,
+    expected: &Tensor,
+    tolerances: &Tolerances,
+) -> Result<NodeComparison> {
+    let mut comparison = NodeComparison {
+        name: name.to_string(),
+        op_name: op_name.to_string(),
+        max_absolute: 0.,
+        max_relative: 0.,
+        ok: true,
+        error: None,
+    };
+
+    if found.datatype() != expected.datatype() {
+        comparison.ok = false;
+        comparison.error = Some(format!(
+            "Expected datatype {:?}, found {:?}.",
+            expected.datatype(), found.datatype()
+        ));
+
+        return Ok(comparison);
+    }
+
+    if found.shape() != expected.shape() {
+        comparison.ok = false;
+        comparison.error = Some(format!("Expected shape {:?}, found {:?}.", expected.shape(), found.shape()));
+
+        return Ok(comparison);
+    }
+
+    let tolerance = tolerances.get(expected.datatype());
+    let mut diverging = 0;
+
+    for (a, b) in to_f64(found)?.into_iter().zip(to_f64(expected)?) {
+        let absolute = (a - b).abs();
+        let relative = if b != 0. { absolute / b.abs() } else if absolute == 0. { 0. } else { ::std::f64::INFINITY };
+
+        comparison.max_absolute = comparison.max_absolute.max(absolute);
+        comparison.max_relative = comparison.max_relative.max(relative);
+
+        if !tolerance.accepts(a, b) {
+            diverging += 1;
+        }
+    }
+
+    if diverging > 0 {
+        comparison.ok = false;
+        comparison.error = Some(format!("{} elements out of {} are too far from the reference.", diverging, found.len()));
+    }
+
+    Ok(comparison)
+}
+
+/// Runs a model and compares the outputs of its nodes with reference values,
+/// e.g. loaded with `load_npz`.
+///
+/// The reference values of the Placeholder nodes needed to compute the
+/// output are used as inputs, and the outputs of all the other nodes which
+/// have a reference value are compared with it.
+pub fn compare(
+    model: &Model,
+    reference: &HashMap<String, Tensor>,
+    output: usize,
+    tolerances: &Tolerances,
+) -> Result<Comparison> {
+    let plan = Plan::for_nodes(&model.nodes, &[output])?.order;
+
+    // Only the inputs which are needed to compute the output must be given.
+    let inputs: HashMap<_, _> = plan
+        .iter()
+        .map(|&n| &model.nodes[n])
+        .filter(|node| node.op_name == "Placeholder")
+        .map(|node| match reference.get(&node.name) {
+            Some(tensor) => Ok((node.id, tensor.clone())),
+            None => bail!("There is no reference value for input {}.", node.name),
+        })
+        .collect::<Result<_>>()?;
+
+    let outputs = eval_with(model, &plan, &inputs, &mut ())?;
+
+    let mut nodes = vec![];
+
+    for &n in &plan {
+        let node = &model.nodes[n];
+        if inputs.contains_key(&n) {
+            continue;
+        }
+
+        let values = match &outputs[n] {
+            Some(values) => values,
+            None => continue,
+        };
+
+        for (port, value) in values.iter().enumerate() {
+            let name = if port == 0 { node.name.clone() } else { format!("{}:{}", node.name, port) };
+
+            if let Some(expected) = reference.get(&name) {
+                nodes.push(compare_tensors(&name, &node.op_name, value.as_tensor(), expected, tolerances)?);
+            }
+        }
+    }
+
+    let first_divergence = nodes.iter().find(|n| !n.ok).map(|n| n.name.clone());
+
+    Ok(Comparison { nodes, first_divergence })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an .npy file with a given version, header and data.
+    fn npy(version: u8, header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x93NUMPY".to_vec();
+        bytes.push(version);
+        bytes.push(0);
+
+        let length = header.len();
+        if version == 1 {
+            bytes.extend(&[length as u8, (length >> 8) as u8]);
+        } else {
+            bytes.extend(&[length as u8, (length >> 8) as u8, (length >> 16) as u8, (length >> 24) as u8]);
+        }
+
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn parse_scalar() {
+        let bytes = npy(1, "{'descr': '<i4', 'fortran_order': False, 'shape': (), }", &[7, 0, 0, 0]);
+        let tensor = parse_npy(&bytes).unwrap();
+
+        assert_eq!(tensor, Tensor::I32(ArrayD::from_elem(vec![], 7)));
+    }
+
+    #[test]
+    fn parse_vector() {
+        let data: Vec<u8> = [1f32, -2., 0.5].iter().flat_map(|v| {
+            let bits = v.to_bits();
+            vec![bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8]
+        }).collect();
+
+        let v1 = npy(1, "{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }", &data);
+        let v2 = npy(2, "{'descr': '<f4', 'fortran_order': False, 'shape': (3,), }", &data);
+        let expected = Tensor::F32(ArrayD::from_shape_vec(vec![3], vec![1., -2., 0.5]).unwrap());
+
+        assert_eq!(parse_npy(&v1).unwrap(), expected);
+        assert_eq!(parse_npy(&v2).unwrap(), expected);
+    }
+
+    #[test]
+    fn fail_on_truncated_file() {
+        let header = "{'descr': '|u1', 'fortran_order': False, 'shape': (2, 2), }";
+        let bytes = npy(1, header, &[1, 2, 3]);
+        assert!(parse_npy(&bytes).is_err());
+
+        let bytes = npy(2, header, &[]);
+        assert!(parse_npy(&bytes[..20]).is_err());
+        assert!(parse_npy(&bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn fail_on_fortran_order() {
+        let bytes = npy(1, "{'descr': '|u1', 'fortran_order': True, 'shape': (2, 2), }", &[1, 2, 3, 4]);
+        assert!(parse_npy(&bytes).is_err());
+    }
+
+    #[test]
+    fn compare_nans() {
+        let exact = Tolerance { absolute: 0., relative: 0., equal_nan: false };
+        let equal_nan = Tolerance { equal_nan: true, ..exact };
+        let nan = ::std::f64::NAN;
+        let infinity = ::std::f64::INFINITY;
+
+        assert!(!exact.accepts(nan, nan));
+        assert!(equal_nan.accepts(nan, nan));
+        assert!(!equal_nan.accepts(nan, 1.));
+        assert!(!equal_nan.accepts(1., nan));
+        assert!(exact.accepts(infinity, infinity));
+        assert!(!exact.accepts(infinity, -infinity));
+    }
+
+    #[test]
+    fn count_diverging_nans() {
+        let found = Tensor::F32(ArrayD::from_shape_vec(vec![2], vec![::std::f32::NAN, 1.]).unwrap());
+        let expected = found.clone();
+        let mut tolerances = Tolerances::default();
+
+        let comparison = compare_tensors("a", "Identity", &found, &expected, &tolerances).unwrap();
+        assert!(!comparison.ok);
+
+        tolerances.0.get_mut(&DataType::DT_FLOAT).unwrap().equal_nan = true;
+        let comparison = compare_tensors("a", "Identity", &found, &expected, &tolerances).unwrap();
+        assert!(comparison.ok);
+    }
+}
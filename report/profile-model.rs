@@ -0,0 +1,520 @@
+use escape;
+use libc;
+use ops::TensorView;
+use std::collections::HashMap;
+use std::{mem, time};
+use {Model, Node, Plan, Result, Tensor};
+
+/// The time taken by an event, in seconds.
+///
+/// The real time is the time elapsed on the wall clock, whereas the user and
+/// sys times are the CPU time spent in user-mode code and kernel calls by all
+/// the threads of the process.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Duration {
+    pub real: f64,
+    pub user: f64,
+    pub sys: f64,
+}
+
+impl Duration {
+    /// Returns the time elapsed since the given instant.
+    pub fn since(start: &Instant) -> Duration {
+        let now = Instant::now();
+
+        Duration {
+            real: to_seconds(now.real.duration_since(start.real)),
+            user: now.user - start.user,
+            sys: now.sys - start.sys,
+        }
+    }
+}
+
+impl ::std::ops::Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration {
+            real: self.real + other.real,
+            user: self.user + other.user,
+            sys: self.sys + other.sys,
+        }
+    }
+}
+
+/// A point in time, on the wall clock and on the CPU clocks of the process.
+#[derive(Debug, Clone, Copy)]
+pub struct Instant {
+    real: time::Instant,
+    user: f64,
+    sys: f64,
+}
+
+impl Instant {
+    /// Returns the current instant.
+    pub fn now() -> Instant {
+        let usage = unsafe {
+            let mut usage: libc::rusage = mem::zeroed();
+            libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+            usage
+        };
+
+        let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+
+        Instant {
+            real: time::Instant::now(),
+            user: seconds(usage.ru_utime),
+            sys: seconds(usage.ru_stime),
+        }
+    }
+}
+
+/// Converts a std::time::Duration into seconds.
+fn to_seconds(duration: time::Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+/// Statistics about a distribution of times, in seconds.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub samples: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub stddev: f64,
+}
+
+impl Stats {
+    /// Computes the statistics of a set of samples.
+    pub fn from_samples(samples: &[f64]) -> Stats {
+        if samples.is_empty() {
+            return Stats::default();
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let variance = sorted.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n;
+
+        Stats {
+            samples: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean,
+            median: percentile(&sorted, 0.5),
+            p90: percentile(&sorted, 0.9),
+            stddev: variance.sqrt(),
+        }
+    }
+}
+
+/// Returns the given percentile of a sorted, non-empty set of samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+/// Statistics about the real, user and sys times of an event.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeStats {
+    pub real: Stats,
+    pub user: Stats,
+    pub sys: Stats,
+
+    // The number of samples which were rejected as outliers.
+    pub outliers: usize,
+}
+
+impl TimeStats {
+    /// Computes the statistics of a set of durations, after removing the
+    /// outliers.
+    ///
+    /// A duration is an outlier when its real time is further away from the
+    /// median than `threshold` times the median absolute deviation.
+    pub fn from_durations(durations: &[Duration], threshold: f64) -> TimeStats {
+        let real: Vec<_> = durations.iter().map(|d| d.real).collect();
+        let median = Stats::from_samples(&real).median;
+        let deviations: Vec<_> = real.iter().map(|r| (r - median).abs()).collect();
+        let deviation = Stats::from_samples(&deviations).median;
+
+        let kept: Vec<_> = durations
+            .iter()
+            .filter(|d| deviation == 0. || (d.real - median).abs() <= threshold * deviation)
+            .collect();
+
+        TimeStats {
+            real: Stats::from_samples(&kept.iter().map(|d| d.real).collect::<Vec<_>>()),
+            user: Stats::from_samples(&kept.iter().map(|d| d.user).collect::<Vec<_>>()),
+            sys: Stats::from_samples(&kept.iter().map(|d| d.sys).collect::<Vec<_>>()),
+            outliers: durations.len() - kept.len(),
+        }
+    }
+}
+
+/// The options of the profiler.
+#[derive(Debug, Clone)]
+pub struct ProfileOptions {
+    // The number of iterations which are run but not measured, to force the
+    // CPU out of throttling.
+    pub warmup: usize,
+
+    // The number of iterations which are measured.
+    pub iterations: usize,
+
+    // The number of median absolute deviations away from the median after
+    // which a sample is considered an outlier.
+    pub threshold: f64,
+}
+
+impl Default for ProfileOptions {
+    fn default() -> ProfileOptions {
+        ProfileOptions {
+            warmup: 10,
+            iterations: 100,
+            threshold: 3.,
+        }
+    }
+}
+
+/// The profile of a single node.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct NodeProfile {
+    pub id: usize,
+    pub name: String,
+    pub op_name: String,
+    pub times: TimeStats,
+}
+
+/// The profile of all the nodes of a given operation type.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct OpProfile {
+    pub op_name: String,
+    pub nodes: usize,
+    pub times: TimeStats,
+}
+
+/// The profile of the evaluation of a model.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub warmup: usize,
+    pub iterations: usize,
+
+    // The profile of each node, in the order of evaluation.
+    pub nodes: Vec<NodeProfile>,
+
+    // The profile of each operation type, by decreasing median real time.
+    pub ops: Vec<OpProfile>,
+
+    // The statistics of the whole evaluation.
+    pub total: TimeStats,
+}
+
+impl Profile {
+    /// Exports the profile as JSON.
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(::serde_json::to_string_pretty(self)?)
+    }
+
+    /// Exports the profile as CSV, with one line per node, one line per
+    /// operation type and one line for the whole evaluation.
+    ///
+    /// All the times are in seconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,name,op,samples,outliers");
+        for clock in &["real", "user", "sys"] {
+            for stat in &["median", "p90", "stddev"] {
+                csv.push_str(&format!(",{}_{}", clock, stat));
+            }
+        }
+
+        csv.push('\n');
+
+        let mut line = |kind: &str, name: &str, op: &str, times: &TimeStats| {
+            csv.push_str(&format!(
+                "{},{},{},{},{}",
+                kind, escape::csv(name), escape::csv(op), times.real.samples, times.outliers
+            ));
+
+            for stats in &[&times.real, &times.user, &times.sys] {
+                csv.push_str(&format!(",{:e},{:e},{:e}", stats.median, stats.p90, stats.stddev));
+            }
+
+            csv.push('\n');
+        };
+
+        for node in &self.nodes {
+            line("node", &node.name, &node.op_name, &node.times);
+        }
+
+        for op in &self.ops {
+            line("op", &op.op_name, &op.op_name, &op.times);
+        }
+
+        line("total", "", "", &self.total);
+        csv
+    }
+}
+
+/// Callbacks which are called during the evaluation of a model, e.g. to
+/// profile or to trace the evaluation.
+pub trait EvalObserver {
+    /// Called before a node is evaluated with the given inputs.
+    fn before(&mut self, _node: &Node, _inputs: &[TensorView]) {}
+
+    /// Called after a node was evaluated, with its outputs.
+    fn after(&mut self, _node: &Node, _outputs: &[TensorView]) {}
+}
+
+impl EvalObserver for () {}
+
+/// Evaluates the nodes of a model in the order of the plan, and notifies
+/// the observer before and after each evaluation.
+///
+/// Returns the outputs of every node in the plan.
+pub fn eval_with(
+    model: &Model,
+    plan: &[usize],
+    inputs: &HashMap<usize, Tensor>,
+    observer: &mut EvalObserver,
+) -> Result<Vec<Option<Vec<TensorView>>>> {
+    let mut outputs: Vec<Option<Vec<TensorView>>> = vec![None; model.nodes.len()];
+
+    for &n in plan {
+        let node = &model.nodes[n];
+
+        if let Some(tensor) = inputs.get(&n) {
+            outputs[n] = Some(vec![tensor.clone().into()]);
+            continue;
+        }
+
+        let mut values = vec![];
+        for &(i, port) in &node.inputs {
+            let value = outputs[i]
+                .as_ref()
+                .and_then(|o| o.get(port.unwrap_or(0)))
+                .ok_or_else(|| format!("Missing input of node {}.", node.name))?;
+
+            values.push(value.clone());
+        }
+
+        observer.before(node, &values);
+        let result = node.op
+            .eval(values)
+            .map_err(|e| format!("While evaluating {}: {}", node.name, e))?;
+        observer.after(node, &result);
+
+        outputs[n] = Some(result);
+    }
+
+    Ok(outputs)
+}
+
+/// An EvalObserver which measures the time taken by each node.
+#[derive(Default)]
+struct Timer {
+    start: Option<Instant>,
+    durations: Vec<(usize, Duration)>,
+}
+
+impl EvalObserver for Timer {
+    fn before(&mut self, _node: &Node, _inputs: &[TensorView]) {
+        self.start = Some(Instant::now());
+    }
+
+    fn after(&mut self, node: &Node, _outputs: &[TensorView]) {
+        let duration = Duration::since(self.start.as_ref().unwrap());
+        self.durations.push((node.id, duration));
+    }
+}
+
+/// Evaluates a model once, and returns the time taken by each node in the
+/// order of the plan.
+fn run_once(
+    model: &Model,
+    plan: &[usize],
+    inputs: &HashMap<usize, Tensor>,
+) -> Result<Vec<(usize, Duration)>> {
+    let mut timer = Timer::default();
+    eval_with(model, plan, inputs, &mut timer)?;
+
+    Ok(timer.durations)
+}
+
+/// Profiles the evaluation of a model given the values of its inputs.
+///
+/// Every node in the plan for the output is evaluated separately, and the
+/// time taken by each evaluation is measured over several iterations after
+/// a few warm-up iterations. The outliers are then removed from the samples
+/// of each node, operation type and of the whole evaluation separately.
+pub fn profile(
+    model: &Model,
+    inputs: Vec<(usize, Tensor)>,
+    output: usize,
+    options: &ProfileOptions,
+) -> Result<Profile> {
+    let plan = Plan::for_nodes(&model.nodes, &[output])?.order;
+    let inputs: HashMap<_, _> = inputs.into_iter().collect();
+
+    for _ in 0..options.warmup {
+        run_once(model, &plan, &inputs)?;
+    }
+
+    let mut by_node: HashMap<usize, Vec<Duration>> = HashMap::new();
+    let mut by_op: HashMap<&str, Vec<Duration>> = HashMap::new();
+    let mut total = vec![];
+
+    for iteration in 0..options.iterations {
+        let durations = run_once(model, &plan, &inputs)?;
+        total.push(durations.iter().fold(Duration::default(), |acc, &(_, d)| acc + d));
+
+        for (n, duration) in durations {
+            by_node.entry(n).or_insert_with(Vec::new).push(duration);
+
+            let samples = by_op.entry(&model.nodes[n].op_name).or_insert_with(Vec::new);
+            if samples.len() <= iteration {
+                samples.push(Duration::default());
+            }
+
+            samples[iteration] = samples[iteration] + duration;
+        }
+    }
+
+    let nodes = plan
+        .iter()
+        .filter_map(|n| by_node.get(n).map(|durations| (&model.nodes[*n], durations)))
+        .map(|(node, durations)| NodeProfile {
+            id: node.id,
+            name: node.name.clone(),
+            op_name: node.op_name.clone(),
+            times: TimeStats::from_durations(durations, options.threshold),
+        })
+        .collect::<Vec<_>>();
+
+    let mut ops = by_op
+        .iter()
+        .map(|(op_name, durations)| OpProfile {
+            op_name: op_name.to_string(),
+            nodes: nodes.iter().filter(|n| n.op_name == *op_name).count(),
+            times: TimeStats::from_durations(durations, options.threshold),
+        })
+        .collect::<Vec<_>>();
+
+    ops.sort_by(|a, b| b.times.real.median.partial_cmp(&a.times.real.median).unwrap());
+
+    Ok(Profile {
+        warmup: options.warmup,
+        iterations: options.iterations,
+        nodes,
+        ops,
+        total: TimeStats::from_durations(&total, options.threshold),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    fn real(samples: &[f64]) -> Vec<Duration> {
+        samples.iter().map(|&real| Duration { real, user: real / 2., sys: 0. }).collect()
+    }
+
+    #[test]
+    fn stats_of_odd_samples() {
+        let stats = Stats::from_samples(&[3., 1., 2., 5., 4.]);
+        assert_eq!(stats.samples, 5);
+        assert_eq!((stats.min, stats.max), (1., 5.));
+        assert_close(stats.mean, 3.);
+        assert_close(stats.median, 3.);
+        assert_close(stats.p90, 4.6);
+        assert_close(stats.stddev, 2f64.sqrt());
+    }
+
+    #[test]
+    fn stats_of_even_samples() {
+        let stats = Stats::from_samples(&[4., 1., 3., 2.]);
+        assert_close(stats.mean, 2.5);
+        assert_close(stats.median, 2.5);
+        assert_close(stats.p90, 3.7);
+        assert_close(stats.stddev, 1.25f64.sqrt());
+    }
+
+    #[test]
+    fn stats_of_single_sample() {
+        let stats = Stats::from_samples(&[7.]);
+        assert_eq!(stats.samples, 1);
+        assert_eq!((stats.min, stats.max, stats.median, stats.p90), (7., 7., 7., 7.));
+        assert_eq!(stats.stddev, 0.);
+    }
+
+    #[test]
+    fn stats_of_no_samples() {
+        assert_eq!(Stats::from_samples(&[]), Stats::default());
+    }
+
+    #[test]
+    fn reject_outliers() {
+        let times = TimeStats::from_durations(&real(&[1., 1.1, 0.9, 1., 10.]), 3.);
+        assert_eq!(times.outliers, 1);
+        assert_eq!(times.real.samples, 4);
+        assert_close(times.real.max, 1.1);
+        assert_close(times.user.max, 0.55);
+    }
+
+    #[test]
+    fn keep_all_samples_without_deviation() {
+        let times = TimeStats::from_durations(&real(&[2., 2., 2.]), 3.);
+        assert_eq!(times.outliers, 0);
+        assert_eq!(times.real.samples, 3);
+        assert_eq!(times.real.stddev, 0.);
+
+        // The median absolute deviation is also zero when most samples are
+        // equal, in which case nothing can be called an outlier either.
+        let times = TimeStats::from_durations(&real(&[1., 1., 1., 5.]), 3.);
+        assert_eq!(times.outliers, 0);
+    }
+
+    #[test]
+    fn export_csv() {
+        let times = TimeStats::from_durations(&[Duration { real: 1., user: 0.5, sys: 0.25 }], 3.);
+        let profile = Profile {
+            warmup: 0,
+            iterations: 1,
+            nodes: vec![NodeProfile {
+                id: 0,
+                name: "conv,1".to_string(),
+                op_name: "Conv2D".to_string(),
+                times: times.clone(),
+            }],
+            ops: vec![OpProfile { op_name: "Conv2D".to_string(), nodes: 1, times: times.clone() }],
+            total: times,
+        };
+
+        let stats = "1e0,1e0,0e0,5e-1,5e-1,0e0,2.5e-1,2.5e-1,0e0";
+        let expected = vec![
+            "kind,name,op,samples,outliers,real_median,real_p90,real_stddev,\
+             user_median,user_p90,user_stddev,sys_median,sys_p90,sys_stddev".to_string(),
+            format!("node,\"conv,1\",Conv2D,1,0,{}", stats),
+            format!("op,Conv2D,Conv2D,1,0,{}", stats),
+            format!("total,,,1,0,{}", stats),
+        ];
+
+        assert_eq!(profile.to_csv().lines().collect::<Vec<_>>(), expected);
+    }
+}
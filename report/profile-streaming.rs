@@ -0,0 +1,231 @@
+use super::{Duration, Instant, ProfileOptions, TimeStats};
+use ops::TensorView;
+use std::collections::HashMap;
+use streaming::observer::StepObserver;
+use streaming::{OpBuffer, StreamingState};
+use tfpb::types::DataType;
+use {Node, Result, Tensor};
+
+/// The profile of a single node during streaming evaluation.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct StreamingNodeProfile {
+    pub id: usize,
+    pub name: String,
+    pub op_name: String,
+
+    // The statistics of the time taken by each call to `step`.
+    pub times: TimeStats,
+
+    // The number of calls to `step` which produced chunks, and which
+    // returned None instead.
+    pub fired: usize,
+    pub skipped: usize,
+
+    // The number of bytes held by the buffer of the node after each of the
+    // chunks used to measure the latency.
+    pub memory: Vec<usize>,
+}
+
+/// The profile of a streaming evaluation.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct StreamingProfile {
+    pub warmup: usize,
+    pub chunks: usize,
+
+    // The profile of each node which was stepped at least once.
+    pub nodes: Vec<StreamingNodeProfile>,
+
+    // The statistics of the time taken to process each chunk.
+    pub latency: TimeStats,
+
+    // The number of chunks processed per second of real time.
+    pub throughput: f64,
+
+    // The number of bytes held by all the buffers after each chunk.
+    pub memory: Vec<usize>,
+}
+
+impl StreamingProfile {
+    /// Returns the maximum number of bytes held by all the buffers.
+    pub fn peak_memory(&self) -> usize {
+        self.memory.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// Exports the profile as JSON.
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(::serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// A StepObserver which measures the time taken by each call to `step`.
+#[derive(Default)]
+struct Profiler {
+    // Whether the measures should be kept, i.e. we're not warming up.
+    recording: bool,
+
+    start: Option<Instant>,
+    durations: HashMap<usize, Vec<Duration>>,
+    fired: HashMap<usize, (usize, usize)>,
+}
+
+impl StepObserver for Profiler {
+    fn before(&mut self, _node: &Node, _inputs: &[(Option<usize>, Option<TensorView>)]) {
+        self.start = Some(Instant::now());
+    }
+
+    fn after(&mut self, node: &Node, fired: bool, _buffer: &OpBuffer) {
+        let duration = Duration::since(self.start.as_ref().unwrap());
+
+        if !self.recording {
+            return;
+        }
+
+        self.durations.entry(node.id).or_insert_with(Vec::new).push(duration);
+
+        let counts = self.fired.entry(node.id).or_insert((0, 0));
+        if fired {
+            counts.0 += 1;
+        } else {
+            counts.1 += 1;
+        }
+    }
+}
+
+/// Profiles the streaming evaluation of a model.
+///
+/// After `options.warmup` chunks which aren't measured, `options.iterations`
+/// random chunks of the given datatype and shape are fed into the input to
+/// measure the time taken by each node. Measuring a node takes two calls to
+/// `getrusage`, which would be counted in the time taken by each chunk, so
+/// `options.iterations` other chunks are then fed without measuring the
+/// nodes, to measure the time taken by each chunk along with the memory
+/// held by the buffers after each chunk.
+pub fn profile_streaming(
+    state: &mut StreamingState,
+    input: usize,
+    datatype: DataType,
+    shape: &[usize],
+    options: &ProfileOptions,
+) -> Result<StreamingProfile> {
+    let mut profiler = Profiler::default();
+
+    for _ in 0..options.warmup {
+        state.step_with(input, Tensor::random(datatype, shape)?, &mut profiler)?;
+    }
+
+    profiler.recording = true;
+
+    for _ in 0..options.iterations {
+        state.step_with(input, Tensor::random(datatype, shape)?, &mut profiler)?;
+    }
+
+    let mut latencies = vec![];
+    let mut buffers: Vec<Vec<usize>> = vec![vec![]; state.model().nodes.len()];
+    let mut memory = vec![];
+
+    for _ in 0..options.iterations {
+        let chunk = Tensor::random(datatype, shape)?;
+
+        let start = Instant::now();
+        state.step(input, chunk)?;
+        latencies.push(Duration::since(&start));
+
+        let usage = state.buffer_memory();
+        for (node, &bytes) in usage.iter().enumerate() {
+            buffers[node].push(bytes);
+        }
+
+        memory.push(usage.iter().sum());
+    }
+
+    let nodes = state
+        .model()
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let durations = profiler.durations.get(&node.id)?;
+            let (fired, skipped) = profiler.fired[&node.id];
+
+            Some(StreamingNodeProfile {
+                id: node.id,
+                name: node.name.clone(),
+                op_name: node.op_name.clone(),
+                times: TimeStats::from_durations(durations, options.threshold),
+                fired,
+                skipped,
+                memory: buffers[node.id].clone(),
+            })
+        })
+        .collect();
+
+    let elapsed: f64 = latencies.iter().map(|d| d.real).sum();
+    let throughput = if elapsed > 0. { latencies.len() as f64 / elapsed } else { 0. };
+
+    Ok(StreamingProfile {
+        warmup: options.warmup,
+        chunks: options.iterations,
+        nodes,
+        latency: TimeStats::from_durations(&latencies, options.threshold),
+        throughput,
+        memory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::ArrayD;
+    use streaming::StreamingInput;
+    use tfpb;
+    use tfpb::types::DataType::DT_FLOAT;
+    use Model;
+
+    /// Builds a convolution over time of a [1, S, 3, 2] input.
+    fn graph() -> tfpb::graph::GraphDef {
+        let filter = Tensor::F32(ArrayD::from_elem(vec![2, 1, 2, 1], 0.5));
+
+        tfpb::graph()
+            .node(tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT))
+            .node(tfpb::node()
+                .name("filter".to_string())
+                .op("Const")
+                .attr("dtype", DT_FLOAT)
+                .attr("value", filter.to_pb().unwrap()))
+            .node(tfpb::node()
+                .name("conv".to_string())
+                .op("Conv2D")
+                .attr("T", DT_FLOAT)
+                .attr("padding", "VALID".to_string())
+                .attr("strides", vec![1, 1, 1, 1])
+                .input("input".to_string())
+                .input("filter".to_string()))
+    }
+
+    #[test]
+    fn profile_conv2d() {
+        let model = Model::new(graph()).unwrap();
+        let input = model.nodes_by_name["input"];
+        let conv = model.nodes_by_name["conv"];
+
+        let streamed = StreamingInput::Streamed(DT_FLOAT, vec![Some(1), None, Some(3), Some(2)]);
+        let mut state = StreamingState::start(model, vec![(input, streamed)], Some(conv)).unwrap();
+
+        let options = ProfileOptions { warmup: 2, iterations: 5, threshold: 3. };
+        let profile = profile_streaming(&mut state, input, DT_FLOAT, &[1, 1, 3, 2], &options).unwrap();
+
+        assert_eq!(profile.chunks, 5);
+        assert_eq!(profile.latency.real.samples + profile.latency.outliers, 5);
+        assert_eq!(profile.memory.len(), 5);
+
+        let node = profile.nodes.iter().find(|n| n.op_name == "Conv2D").unwrap();
+        assert_eq!(node.fired + node.skipped, 5);
+        assert_eq!(node.memory.len(), 5);
+
+        // The buffer of the convolution holds at least the previous chunk.
+        assert!(node.memory.iter().all(|&bytes| bytes >= 3 * 2 * 4));
+        assert!(profile.peak_memory() >= 3 * 2 * 4);
+    }
+}
@@ -0,0 +1,276 @@
+//! Traces of evaluations in the Chrome Trace Event format.
+//!
+//! The traces are written with serde, so this module is only built with the
+//! `serialize` feature.
+
+use super::{eval_with, EvalObserver};
+use ops::TensorView;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time;
+use streaming::observer::StepObserver;
+use streaming::{OpBuffer, StreamingState};
+use {Model, Node, Plan, Result, Tensor};
+
+/// A slice of a trace, i.e. the evaluation or the step of a single node.
+#[derive(Debug, Clone)]
+struct Slice {
+    name: String,
+    op_name: String,
+    shapes: Vec<Option<Vec<usize>>>,
+    chunk: Option<usize>,
+    fired: Option<bool>,
+
+    // The start and the duration of the slice, in microseconds.
+    start: f64,
+    duration: f64,
+}
+
+/// A trace in the Chrome Trace Event format.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: Vec<Event<'a>>,
+    display_time_unit: &'static str,
+}
+
+/// A complete event of the trace, i.e. a slice with its duration.
+#[derive(Serialize)]
+struct Event<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: usize,
+    tid: usize,
+    args: Args<'a>,
+}
+
+/// The arguments of an event, which are shown when selecting it.
+#[derive(Serialize)]
+struct Args<'a> {
+    op: &'a str,
+    inputs: &'a [Option<Vec<usize>>],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fired: Option<bool>,
+}
+
+/// A recorder of the evaluation of a model, which can be saved in the Chrome
+/// Trace Event format and opened in chrome://tracing or Perfetto.
+///
+/// It can be used both during a regular evaluation, e.g. with:
+/// ```text
+/// let mut writer = TraceWriter::new();
+/// eval_with(&model, &plan, &inputs, &mut writer)?;
+/// writer.save("eval.json")?;
+/// ```
+///
+/// and during a streaming evaluation, in which case each slice is tagged
+/// with the index of the chunk which was fed to the model:
+/// ```text
+/// for chunk in chunks {
+///     writer.next_chunk();
+///     state.step_with(input, chunk, &mut writer)?;
+/// }
+/// ```
+pub struct TraceWriter {
+    origin: time::Instant,
+    chunk: Option<usize>,
+    current: Option<Slice>,
+    slices: Vec<Slice>,
+}
+
+impl TraceWriter {
+    /// Creates a new TraceWriter instance.
+    pub fn new() -> TraceWriter {
+        TraceWriter {
+            origin: time::Instant::now(),
+            chunk: None,
+            current: None,
+            slices: vec![],
+        }
+    }
+
+    /// Tags the next slices with the index of a new chunk.
+    pub fn next_chunk(&mut self) {
+        self.chunk = Some(self.chunk.map(|c| c + 1).unwrap_or(0));
+    }
+
+    /// Returns the time elapsed since the creation of the writer, in
+    /// microseconds.
+    fn now(&self) -> f64 {
+        let elapsed = self.origin.elapsed();
+        elapsed.as_secs() as f64 * 1e6 + elapsed.subsec_nanos() as f64 / 1e3
+    }
+
+    /// Starts a new slice for the given node.
+    fn start(&mut self, node: &Node, shapes: Vec<Option<Vec<usize>>>) {
+        self.current = Some(Slice {
+            name: node.name.clone(),
+            op_name: node.op_name.clone(),
+            shapes,
+            chunk: self.chunk,
+            fired: None,
+            start: self.now(),
+            duration: 0.,
+        });
+    }
+
+    /// Ends the current slice.
+    fn end(&mut self, fired: Option<bool>) {
+        let now = self.now();
+
+        if let Some(mut slice) = self.current.take() {
+            slice.duration = now - slice.start;
+            slice.fired = fired;
+            self.slices.push(slice);
+        }
+    }
+
+    /// Returns the trace in the Chrome Trace Event format.
+    pub fn to_json(&self) -> Result<String> {
+        let events = self
+            .slices
+            .iter()
+            .map(|slice| Event {
+                name: &slice.name,
+                cat: &slice.op_name,
+                ph: "X",
+                ts: slice.start,
+                dur: slice.duration,
+                pid: 1,
+                tid: 1,
+                args: Args {
+                    op: &slice.op_name,
+                    inputs: &slice.shapes,
+                    chunk: slice.chunk,
+                    fired: slice.fired,
+                },
+            })
+            .collect();
+
+        let trace = Trace {
+            trace_events: events,
+            display_time_unit: "ms",
+        };
+
+        Ok(::serde_json::to_string(&trace)?)
+    }
+
+    /// Saves the trace to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.to_json()?.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl EvalObserver for TraceWriter {
+    fn before(&mut self, node: &Node, inputs: &[TensorView]) {
+        let shapes = inputs.iter().map(|i| Some(i.as_tensor().shape().to_vec())).collect();
+        self.start(node, shapes);
+    }
+
+    fn after(&mut self, _node: &Node, _outputs: &[TensorView]) {
+        self.end(None);
+    }
+}
+
+impl StepObserver for TraceWriter {
+    fn before(&mut self, node: &Node, inputs: &[(Option<usize>, Option<TensorView>)]) {
+        let shapes = inputs
+            .iter()
+            .map(|(_, chunk)| chunk.as_ref().map(|c| c.as_tensor().shape().to_vec()))
+            .collect();
+
+        self.start(node, shapes);
+    }
+
+    fn after(&mut self, _node: &Node, fired: bool, _buffer: &OpBuffer) {
+        self.end(Some(fired));
+    }
+}
+
+/// Evaluates a model, and saves a trace of the evaluation to a file.
+pub fn trace_eval<P: AsRef<Path>>(
+    model: &Model,
+    inputs: Vec<(usize, Tensor)>,
+    output: usize,
+    path: P,
+) -> Result<()> {
+    let plan = Plan::for_nodes(&model.nodes, &[output])?.order;
+    let inputs: HashMap<_, _> = inputs.into_iter().collect();
+
+    let mut writer = TraceWriter::new();
+    eval_with(model, &plan, &inputs, &mut writer)?;
+    writer.save(path)
+}
+
+/// Feeds chunks to a streaming evaluation, and saves a trace of the steps
+/// to a file.
+pub fn trace_streaming<P: AsRef<Path>>(
+    state: &mut StreamingState,
+    input: usize,
+    chunks: Vec<Tensor>,
+    path: P,
+) -> Result<()> {
+    let mut writer = TraceWriter::new();
+
+    for chunk in chunks {
+        writer.next_chunk();
+        state.step_with(input, chunk, &mut writer)?;
+    }
+
+    writer.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ops::OpBuilder;
+    use serde_json::{self, Value};
+    use tfpb;
+    use tfpb::types::DataType::DT_FLOAT;
+
+    fn node(name: &str) -> Node {
+        let node_def = tfpb::node().name(name.to_string()).op("Identity").attr("T", DT_FLOAT);
+
+        Node {
+            id: 0,
+            name: name.to_string(),
+            op_name: "Identity".to_string(),
+            inputs: vec![],
+            op: OpBuilder::new().build(&node_def).unwrap(),
+        }
+    }
+
+    #[test]
+    fn write_valid_json() {
+        let mut writer = TraceWriter::new();
+        writer.start(&node("first"), vec![Some(vec![1, 2])]);
+        writer.end(None);
+
+        writer.next_chunk();
+        writer.start(&node("a \"quoted\"\nname"), vec![Some(vec![1, 2]), None]);
+        writer.end(Some(true));
+
+        let json: Value = serde_json::from_str(&writer.to_json().unwrap()).unwrap();
+        assert_eq!(json["displayTimeUnit"], "ms");
+
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0]["args"].get("chunk").is_none());
+        assert!(events[0]["args"].get("fired").is_none());
+
+        assert_eq!(events[1]["name"], "a \"quoted\"\nname");
+        assert_eq!(events[1]["ph"], "X");
+        assert_eq!(events[1]["args"]["inputs"].to_string(), "[[1,2],null]");
+        assert_eq!(events[1]["args"]["chunk"], 0);
+        assert_eq!(events[1]["args"]["fired"], true);
+    }
+}
@@ -110,6 +110,17 @@ impl StreamingState {
     /// a Vec<Tensor> for every chunk that was produced by the output
     /// during the evaluation step, with one Tensor per output port.
     pub fn step(&mut self, input: usize, input_chunk: Tensor) -> Result<Vec<Vec<Tensor>>> {
+        self.step_with(input, input_chunk, &mut ())
+    }
+
+    /// Runs one streaming evaluation step, and notifies the observer before
+    /// and after each node is stepped.
+    pub fn step_with(
+        &mut self,
+        input: usize,
+        input_chunk: Tensor,
+        observer: &mut StepObserver,
+    ) -> Result<Vec<Vec<Tensor>>> {
         let mut queue = VecDeque::new();
         let mut outputs = vec![];
 
@@ -166,7 +177,11 @@ impl StreamingState {
 
             let buffer = &mut self.buffers[target.id];
 
-            if let Some(mut output_chunks) = target.op.step(inputs, buffer)? {
+            observer.before(target, &inputs);
+            let result = target.op.step(inputs, buffer)?;
+            observer.after(target, result.is_some(), &**buffer);
+
+            if let Some(mut output_chunks) = result {
                 if target.id == self.output {
                     // If we've reached the output, just save the chunks.
                     outputs.push(output_chunks.clone());
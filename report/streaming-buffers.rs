@@ -0,0 +1,89 @@
+/// A buffer with a variable type, which an operation can use to store its
+/// state between two streaming evaluation steps.
+pub trait OpBuffer: Downcast + Debug + objekt::Clone + Send + 'static {
+    /// Returns the number of bytes of data held by the buffer.
+    ///
+    /// This is used by the streaming profiler, which would otherwise
+    /// silently underestimate the memory used by the evaluation.
+    fn memory(&self) -> usize;
+}
+
+clone_trait_object!(OpBuffer);
+impl_downcast!(OpBuffer);
+
+/// Returns the number of bytes of data held by a tensor.
+pub fn tensor_memory(tensor: &Tensor) -> usize {
+    match tensor {
+        Tensor::F32(a) => a.len() * mem::size_of::<f32>(),
+        Tensor::F64(a) => a.len() * mem::size_of::<f64>(),
+        Tensor::I32(a) => a.len() * mem::size_of::<i32>(),
+        Tensor::I8(a) => a.len() * mem::size_of::<i8>(),
+        Tensor::U8(a) => a.len() * mem::size_of::<u8>(),
+        Tensor::String(a) => a.len() * mem::size_of::<i8>(),
+    }
+}
+
+/// An empty buffer for operations which don't need one.
+#[derive(Debug, Clone)]
+pub struct EmptyBuffer {}
+
+impl OpBuffer for EmptyBuffer {
+    /// Returns the number of bytes of data held by the buffer.
+    fn memory(&self) -> usize {
+        0
+    }
+}
+
+/// A buffer with one queue per input.
+#[derive(Debug, Clone, new)]
+pub struct QueuesBuffer(Vec<VecDeque<TensorView>>);
+
+impl OpBuffer for QueuesBuffer {
+    /// Returns the number of bytes of data held by the buffer.
+    fn memory(&self) -> usize {
+        self.0
+            .iter()
+            .flat_map(|queue| queue.iter())
+            .map(|chunk| tensor_memory(chunk.as_tensor()))
+            .sum()
+    }
+}
+
+/// The buffer of the streaming Conv2D operation.
+#[derive(Debug, Clone, new)]
+pub struct Buffer<T: Datum> {
+    // The chunks which were received but not consumed yet.
+    prev: Option<Array4<T>>,
+
+    // The number of chunks to skip before the next convolution.
+    skip: usize,
+}
+
+impl<T: Datum> OpBuffer for Buffer<T> {
+    /// Returns the number of bytes of data held by the buffer.
+    fn memory(&self) -> usize {
+        self.prev.as_ref().map(|p| p.len() * mem::size_of::<T>()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::ArrayD;
+
+    #[test]
+    fn memory_of_queues() {
+        let chunk = |len| -> TensorView { Tensor::F32(ArrayD::zeros(vec![1, len])).into() };
+        let queues = vec![VecDeque::from(vec![chunk(3), chunk(2)]), VecDeque::new(), VecDeque::from(vec![chunk(1)])];
+
+        assert_eq!(QueuesBuffer::new(queues).memory(), 6 * 4);
+        assert_eq!(QueuesBuffer::new(vec![]).memory(), 0);
+    }
+
+    #[test]
+    fn memory_of_conv2d_buffer() {
+        assert_eq!(Buffer::<f32>::new(Some(Array4::zeros((1, 2, 3, 4))), 0).memory(), 24 * 4);
+        assert_eq!(Buffer::<f64>::new(Some(Array4::zeros((1, 2, 3, 4))), 0).memory(), 24 * 8);
+        assert_eq!(Buffer::<f32>::new(None, 1).memory(), 0);
+    }
+}
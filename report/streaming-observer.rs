@@ -0,0 +1,29 @@
+use ops::TensorView;
+use streaming::{OpBuffer, StreamingState};
+use {Model, Node};
+
+/// Callbacks which are called during streaming evaluation steps, e.g. to
+/// profile or to trace the evaluation.
+pub trait StepObserver {
+    /// Called before a node is stepped with the given inputs.
+    fn before(&mut self, _node: &Node, _inputs: &[(Option<usize>, Option<TensorView>)]) {}
+
+    /// Called after a node was stepped, with whether it produced chunks and
+    /// the state of its buffer.
+    fn after(&mut self, _node: &Node, _fired: bool, _buffer: &OpBuffer) {}
+}
+
+impl StepObserver for () {}
+
+impl StreamingState {
+    /// Returns the model being evaluated, which might differ from the one
+    /// given to `start` as its constant parts were folded.
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Returns the number of bytes of data held by the buffer of each node.
+    pub fn buffer_memory(&self) -> Vec<usize> {
+        self.buffers.iter().map(|b| b.memory()).collect()
+    }
+}
@@ -0,0 +1,41 @@
+use ndarray::prelude::*;
+use rand::{self, Rng};
+use tfpb::types::DataType;
+use {Result, Tensor};
+
+impl Tensor {
+    /// Generates a random tensor of the given datatype and shape.
+    ///
+    /// Floating point elements are drawn from [-1, 1) and 32-bit integers
+    /// from [-100, 100), so that they stay usable as e.g. sizes or indices.
+    pub fn random(datatype: DataType, shape: &[usize]) -> Result<Tensor> {
+        let mut rng = rand::thread_rng();
+
+        let tensor = match datatype {
+            DataType::DT_FLOAT => Tensor::F32(ArrayD::from_shape_fn(shape, |_| rng.gen_range(-1., 1.))),
+            DataType::DT_DOUBLE => Tensor::F64(ArrayD::from_shape_fn(shape, |_| rng.gen_range(-1., 1.))),
+            DataType::DT_INT32 => Tensor::I32(ArrayD::from_shape_fn(shape, |_| rng.gen_range(-100, 100))),
+            DataType::DT_INT8 => Tensor::I8(ArrayD::from_shape_fn(shape, |_| rng.gen())),
+            DataType::DT_UINT8 => Tensor::U8(ArrayD::from_shape_fn(shape, |_| rng.gen())),
+            _ => bail!("Can't generate random tensors of type {:?}.", datatype),
+        };
+
+        Ok(tensor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_tensors_have_requested_shape() {
+        for &datatype in &[DataType::DT_FLOAT, DataType::DT_INT32, DataType::DT_UINT8] {
+            let tensor = Tensor::random(datatype, &[2, 0, 3]).unwrap();
+            assert_eq!(tensor.shape().to_vec(), vec![2, 0, 3]);
+            assert_eq!(tensor.datatype(), datatype);
+        }
+
+        assert!(Tensor::random(DataType::DT_STRING, &[2]).is_err());
+    }
+}
@@ -0,0 +1,489 @@
+use analyser::visualizer::scopes;
+use cassowary::strength::{REQUIRED, STRONG, WEAK};
+use cassowary::WeightedRelation::{EQ, GE, LE};
+use cassowary::{Constraint, Solver, Variable};
+use escape;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use {Model, Result};
+
+/// The dimensions of the layout, in pixels.
+const NODE_HEIGHT: f64 = 36.;
+const MIN_NODE_WIDTH: f64 = 80.;
+const CHAR_WIDTH: f64 = 7.;
+const LAYER_SPACING: f64 = 40.;
+const NODE_SPACING: f64 = 20.;
+const GROUP_PADDING: f64 = 10.;
+
+/// The position of a node in the layout, with (x, y) its top-left corner.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct NodeBox {
+    pub id: usize,
+    pub name: String,
+    pub op_name: String,
+    pub layer: usize,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The position of a name-scope group in the layout.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct GroupBox {
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The layout of a dataflow graph.
+#[cfg_attr(feature = "serialize", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub nodes: Vec<NodeBox>,
+    pub groups: Vec<GroupBox>,
+    pub edges: Vec<(usize, usize)>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Returns the layer of every node, i.e. the length of the longest path
+/// from a node without inputs to the node.
+fn layers(model: &Model) -> Result<Vec<usize>> {
+    let n = model.nodes.len();
+    let mut successors = vec![vec![]; n];
+    let mut remaining = vec![0; n];
+
+    for node in &model.nodes {
+        for &(input, _) in &node.inputs {
+            successors[input].push(node.id);
+            remaining[node.id] += 1;
+        }
+    }
+
+    let mut layers = vec![0; n];
+    let mut queue: VecDeque<_> = (0..n).filter(|&i| remaining[i] == 0).collect();
+    let mut visited = 0;
+
+    while let Some(i) = queue.pop_front() {
+        visited += 1;
+
+        for &j in &successors[i] {
+            layers[j] = layers[j].max(layers[i] + 1);
+            remaining[j] -= 1;
+
+            if remaining[j] == 0 {
+                queue.push_back(j);
+            }
+        }
+    }
+
+    if visited != n {
+        bail!("The graph contains a cycle, so it can't be laid out in layers.");
+    }
+
+    Ok(layers)
+}
+
+/// Orders the nodes of every layer so that the nodes of a group are next to
+/// each other, and so that nodes are roughly below their inputs.
+///
+/// Each node gets the barycenter of the positions of its inputs, and each
+/// group the mean barycenter of its nodes in the layer. Nodes are then
+/// sorted by the barycenters of their enclosing groups, from the outermost
+/// to the innermost, and finally by their own barycenter.
+fn order(model: &Model, layers: &[usize], node_scopes: &[Vec<String>]) -> Vec<Vec<usize>> {
+    let depth = layers.iter().cloned().max().map(|d| d + 1).unwrap_or(0);
+    let mut ordered = vec![vec![]; depth];
+    let mut position = vec![0.; model.nodes.len()];
+
+    for layer in 0..depth {
+        let members: Vec<_> = (0..model.nodes.len()).filter(|&i| layers[i] == layer).collect();
+
+        let barycenter: HashMap<usize, f64> = members
+            .iter()
+            .map(|&i| {
+                let inputs = &model.nodes[i].inputs;
+                let b = if inputs.is_empty() {
+                    i as f64
+                } else {
+                    inputs.iter().map(|&(j, _)| position[j]).sum::<f64>() / inputs.len() as f64
+                };
+
+                (i, b)
+            })
+            .collect();
+
+        let mut groups: HashMap<String, (f64, usize)> = HashMap::new();
+        for &i in &members {
+            for scope in &node_scopes[i] {
+                let entry = groups.entry(scope.clone()).or_insert((0., 0));
+                entry.0 += barycenter[&i];
+                entry.1 += 1;
+            }
+        }
+
+        let key = |i: usize| -> Vec<(f64, String)> {
+            let mut key: Vec<_> = node_scopes[i]
+                .iter()
+                .map(|s| (groups[s].0 / groups[s].1 as f64, s.clone()))
+                .collect();
+
+            key.push((barycenter[&i], model.nodes[i].name.clone()));
+            key
+        };
+
+        let mut keyed: Vec<_> = members.iter().map(|&i| (key(i), i)).collect();
+        keyed.sort_by(|a, b| {
+            for (x, y) in a.0.iter().zip(&b.0) {
+                match x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal).then_with(|| x.1.cmp(&y.1)) {
+                    Ordering::Equal => continue,
+                    ordering => return ordering,
+                }
+            }
+
+            a.0.len().cmp(&b.0.len())
+        });
+
+        ordered[layer] = keyed.into_iter().map(|(_, i)| i).collect();
+        for (k, &i) in ordered[layer].iter().enumerate() {
+            position[i] = k as f64;
+        }
+    }
+
+    ordered
+}
+
+/// Computes the layout of a dataflow graph.
+///
+/// As in the visualizer, the layout is expressed as a set of linear
+/// constraints which are solved with the Cassowary algorithm:
+/// - the nodes are placed in layers, so that every node is below its inputs;
+/// - the nodes of a layer are kept in order, without overlapping;
+/// - the nodes of a name scope are kept inside the box of the scope, and
+///   the other nodes outside of it;
+/// - every node is pulled towards its inputs, and boxes towards their
+///   smallest possible width.
+///
+/// The constraints that nodes stay outside of the boxes of other scopes are
+/// only strong, not required, as they can be contradictory on some graphs.
+/// Since the order of the nodes in a layer is required, only the nearest
+/// node on each side of a box needs such a constraint.
+pub fn layout(model: &Model) -> Result<Layout> {
+    let layers = layers(model)?;
+    let node_scopes: Vec<Vec<String>> = model.nodes.iter().map(|n| scopes(&n.name)).collect();
+    let ordered = order(model, &layers, &node_scopes);
+
+    let mut position = vec![0; model.nodes.len()];
+    for layer in &ordered {
+        for (k, &i) in layer.iter().enumerate() {
+            position[i] = k;
+        }
+    }
+
+    let widths: Vec<f64> = model
+        .nodes
+        .iter()
+        .map(|n| {
+            let name = n.name.rsplit('/').next().unwrap_or(&n.name);
+            (name.len().max(n.op_name.len()) as f64 * CHAR_WIDTH + 2. * NODE_SPACING).max(MIN_NODE_WIDTH)
+        })
+        .collect();
+
+    // The variables are the horizontal centers of the nodes, and the
+    // horizontal bounds of the boxes of the groups.
+    let x: Vec<_> = model.nodes.iter().map(|_| Variable::new()).collect();
+    let mut bounds: BTreeMap<String, (Variable, Variable)> = BTreeMap::new();
+    let mut constraints: Vec<Constraint> = vec![];
+
+    for node in &model.nodes {
+        let i = node.id;
+        constraints.push(x[i] - widths[i] / 2. | GE(REQUIRED) | 0.);
+
+        for &(j, _) in &node.inputs {
+            constraints.push(x[i] | EQ(WEAK) | x[j]);
+        }
+
+        // The box of every enclosing group contains the node, and the box
+        // of a group is contained in the box of its parent.
+        let scopes = &node_scopes[i];
+        for (k, scope) in scopes.iter().enumerate() {
+            let (left, right) = *bounds
+                .entry(scope.clone())
+                .or_insert_with(|| (Variable::new(), Variable::new()));
+
+            let padding = GROUP_PADDING * (scopes.len() - k) as f64;
+            constraints.push(left | LE(REQUIRED) | x[i] - widths[i] / 2. - padding);
+            constraints.push(right | GE(REQUIRED) | x[i] + widths[i] / 2. + padding);
+        }
+    }
+
+    for &(left, right) in bounds.values() {
+        constraints.push(right | EQ(WEAK * 0.1) | left);
+    }
+
+    // The first and last layers which contain a member of each group.
+    let mut extents: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for node in &model.nodes {
+        for scope in &node_scopes[node.id] {
+            let extent = extents.entry(&scope[..]).or_insert((layers[node.id], layers[node.id]));
+            extent.0 = extent.0.min(layers[node.id]);
+            extent.1 = extent.1.max(layers[node.id]);
+        }
+    }
+
+    for layer in &ordered {
+        for pair in layer.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let gap = (widths[a] + widths[b]) / 2. + NODE_SPACING;
+            constraints.push(x[b] | GE(REQUIRED) | x[a] + gap);
+        }
+    }
+
+    for (scope, &(left, right)) in &bounds {
+        let (top, bottom) = extents[&scope[..]];
+        let mut center = 0.;
+
+        for depth in top..bottom + 1 {
+            let layer = &ordered[depth];
+            let inside: Vec<usize> = layer.iter().cloned().filter(|&i| node_scopes[i].contains(scope)).collect();
+
+            // The nodes of a group are contiguous in the layer, so the other
+            // nodes are either on its left or on its right. In the layers
+            // without any member, the box still crosses the layer, and the
+            // nodes are split around the position of the group in the
+            // nearest layer above.
+            let (first, end) = match (inside.first(), inside.last()) {
+                (Some(&first), Some(&last)) => {
+                    center = inside.iter().map(|&i| position[i] as f64).sum::<f64>() / inside.len() as f64;
+                    (position[first], position[last] + 1)
+                }
+                _ => {
+                    let split = layer.iter().filter(|&&i| (position[i] as f64) < center).count();
+                    (split, split)
+                }
+            };
+
+            if first > 0 {
+                let i = layer[first - 1];
+                constraints.push(x[i] + widths[i] / 2. + NODE_SPACING | LE(STRONG) | left);
+            }
+
+            if let Some(&i) = layer.get(end) {
+                constraints.push(x[i] - widths[i] / 2. - NODE_SPACING | GE(STRONG) | right);
+            }
+        }
+    }
+
+    let mut solver = Solver::new();
+    solver
+        .add_constraints(&constraints)
+        .map_err(|e| format!("Unable to solve the layout constraints: {:?}.", e))?;
+
+    let y = |layer: usize| layer as f64 * (NODE_HEIGHT + LAYER_SPACING);
+
+    let nodes: Vec<_> = model
+        .nodes
+        .iter()
+        .map(|node| NodeBox {
+            id: node.id,
+            name: node.name.clone(),
+            op_name: node.op_name.clone(),
+            layer: layers[node.id],
+            x: solver.get_value(x[node.id]) - widths[node.id] / 2.,
+            y: y(layers[node.id]),
+            width: widths[node.id],
+            height: NODE_HEIGHT,
+        })
+        .collect();
+
+    let groups: Vec<_> = bounds
+        .iter()
+        .map(|(scope, &(left, right))| {
+            let members: Vec<_> = nodes.iter().filter(|n| node_scopes[n.id].contains(scope)).collect();
+            let depth = scope.split('/').count() as f64;
+            let top = members.iter().map(|n| n.y).fold(::std::f64::INFINITY, f64::min);
+            let bottom = members.iter().map(|n| n.y + n.height).fold(0., f64::max);
+            let padding = GROUP_PADDING * (members.iter().map(|n| node_scopes[n.id].len()).max().unwrap_or(1) as f64 - depth + 1.);
+
+            GroupBox {
+                name: scope.clone(),
+                x: solver.get_value(left),
+                y: top - padding,
+                width: solver.get_value(right) - solver.get_value(left),
+                height: bottom - top + 2. * padding,
+            }
+        })
+        .collect();
+
+    let edges = model
+        .nodes
+        .iter()
+        .flat_map(|node| node.inputs.iter().map(move |&(j, _)| (j, node.id)))
+        .collect();
+
+    let width = nodes.iter().map(|n| n.x + n.width).fold(0., f64::max) + GROUP_PADDING;
+    let height = nodes.iter().map(|n| n.y + n.height).fold(0., f64::max) + GROUP_PADDING;
+
+    Ok(Layout { nodes, groups, edges, width, height })
+}
+
+impl Layout {
+    /// Renders the layout as an SVG document.
+    pub fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let margin = GROUP_PADDING * 2.;
+
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" font-family=\"sans-serif\" font-size=\"11\">",
+            -margin, -margin, self.width + 2. * margin, self.height + 2. * margin
+        ).unwrap();
+
+        for group in &self.groups {
+            writeln!(
+                svg,
+                "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" fill=\"#000\" fill-opacity=\"0.04\" stroke=\"#bbb\"/>",
+                group.x, group.y, group.width, group.height
+            ).unwrap();
+
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#888\">{}</text>",
+                group.x + 4., group.y - 3., escape::xml(group.name.rsplit('/').next().unwrap_or(&group.name))
+            ).unwrap();
+        }
+
+        for &(from, to) in &self.edges {
+            let (a, b) = (&self.nodes[from], &self.nodes[to]);
+            let (x1, y1) = (a.x + a.width / 2., a.y + a.height);
+            let (x2, y2) = (b.x + b.width / 2., b.y);
+            let middle = (y1 + y2) / 2.;
+
+            writeln!(
+                svg,
+                "  <path d=\"M {:.1} {:.1} C {:.1} {:.1}, {:.1} {:.1}, {:.1} {:.1}\" fill=\"none\" stroke=\"#666\"/>",
+                x1, y1, x1, middle, x2, middle, x2, y2
+            ).unwrap();
+        }
+
+        for node in &self.nodes {
+            let name = node.name.rsplit('/').next().unwrap_or(&node.name);
+
+            writeln!(
+                svg,
+                "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"4\" fill=\"#fff\" stroke=\"#333\"/>",
+                node.x, node.y, node.width, node.height
+            ).unwrap();
+
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\"><tspan x=\"{:.1}\" dy=\"0\">{}</tspan><tspan x=\"{:.1}\" dy=\"13\" fill=\"#888\">{}</tspan></text>",
+                node.x + node.width / 2., node.y + 15., node.x + node.width / 2., escape::xml(name),
+                node.x + node.width / 2., escape::xml(&node.op_name)
+            ).unwrap();
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfpb;
+    use tfpb::node_def::NodeDef;
+    use tfpb::types::DataType::DT_FLOAT;
+
+    fn node(name: &str, op: &str, inputs: &[&str]) -> NodeDef {
+        inputs
+            .iter()
+            .fold(tfpb::node().name(name.to_string()).op(op).attr("T", DT_FLOAT), |node, i| {
+                node.input(i.to_string())
+            })
+    }
+
+    fn model(nodes: Vec<NodeDef>) -> Model {
+        let graph = nodes.into_iter().fold(tfpb::graph(), |graph, node| graph.node(node));
+        Model::new(graph).unwrap()
+    }
+
+    /// Builds a graph with nested groups, and nodes outside of the groups
+    /// in the same layers.
+    fn nested() -> Model {
+        model(vec![
+            tfpb::node().name("input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT),
+            node("block/relu", "Relu", &["input"]),
+            node("other", "Relu", &["input"]),
+            node("block/inner/a", "Relu", &["block/relu"]),
+            node("block/inner/b", "Tanh", &["block/relu"]),
+            node("side", "Relu", &["other"]),
+            node("block/add", "Add", &["block/inner/a", "block/inner/b"]),
+            node("output", "Add", &["block/add", "side"]),
+        ])
+    }
+
+    /// Returns whether two intervals overlap, with a tolerance for the
+    /// rounding errors of the solver.
+    fn overlap(a: (f64, f64), b: (f64, f64)) -> bool {
+        a.0 < b.1 - 1e-6 && b.0 < a.1 - 1e-6
+    }
+
+    #[test]
+    fn no_overlap_in_layers() {
+        let layout = layout(&nested()).unwrap();
+
+        for a in &layout.nodes {
+            for b in &layout.nodes {
+                if a.id < b.id && a.layer == b.layer {
+                    assert!(!overlap((a.x, a.x + a.width), (b.x, b.x + b.width)), "{:?} overlaps {:?}.", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn members_inside_their_group() {
+        let layout = layout(&nested()).unwrap();
+        assert_eq!(layout.groups.len(), 2);
+
+        for group in &layout.groups {
+            let members = layout.nodes.iter().filter(|n| scopes(&n.name).contains(&group.name));
+            for node in members {
+                assert!(group.x <= node.x + 1e-6 && node.x + node.width <= group.x + group.width + 1e-6);
+                assert!(group.y <= node.y && node.y + node.height <= group.y + group.height);
+            }
+
+            // The other nodes in the layers crossed by the box stay outside.
+            let others = layout.nodes.iter().filter(|n| {
+                !scopes(&n.name).contains(&group.name) && n.y < group.y + group.height && group.y < n.y + n.height
+            });
+
+            for node in others {
+                assert!(!overlap((group.x, group.x + group.width), (node.x, node.x + node.width)));
+            }
+        }
+    }
+
+    #[test]
+    fn keep_layers_without_members_outside_of_groups() {
+        // The group spans the three layers, but only has members in the
+        // first and the last one.
+        let layout = layout(&model(vec![
+            tfpb::node().name("group/input".to_string()).op("Placeholder").attr("dtype", DT_FLOAT),
+            node("relu", "Relu", &["group/input"]),
+            node("group/tanh", "Tanh", &["relu"]),
+        ])).unwrap();
+
+        let group = &layout.groups[0];
+        let relu = layout.nodes.iter().find(|n| n.name == "relu").unwrap();
+        assert_eq!(relu.layer, 1);
+        assert!(!overlap((group.x, group.x + group.width), (relu.x, relu.x + relu.width)));
+    }
+}